@@ -1,13 +1,19 @@
-use std::ffi::OsStr;
-use std::io::Read;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::io::Result;
 use std::path::{Path, PathBuf};
-use std::process::{Child, Command, Stdio};
+use std::ops::{Deref, DerefMut};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
 use num_traits::AsPrimitive;
 use inlinable_string::InlinableString;
 
 macro_rules! bool_option {
-    ($name: ident) => {
+    ($(#[$attr: meta])* $name: ident) => {
+        $(#[$attr])*
         pub fn $name(&mut self) -> &mut Self {
             self.profile.$name = true;
             self
@@ -33,13 +39,347 @@ macro_rules! path_option_replace {
     };
 }
 
+macro_rules! option_unset {
+    ($name: ident, $field: ident) => {
+        pub fn $name(&mut self) -> &mut Self {
+            self.profile.$field = None;
+            self
+        }
+    };
+}
+
 pub struct FireJailCommand {
-    inner: Command,
-    executable: InlinableString,
-    arg_vec: Vec<InlinableString>,
+    executable: OsString,
+    arg_vec: Vec<OsString>,
     profile: Profile,
+    sorted: bool,
+    warn_on_suspicious_args: bool,
+    current_dir: Option<PathBuf>,
+    env_clear: bool,
+    envs: Vec<(OsString, Option<OsString>)>,
+    stdin: Option<Stdio>,
+    stdout: Option<Stdio>,
+    stderr: Option<Stdio>,
+    on_exit: Vec<ExitHook>,
+    kill_on_drop: bool,
+    /// Set once the command is spawned, or its profile is moved elsewhere.
+    consumed: Cell<bool>,
+}
+
+type ExitHook = Box<dyn FnOnce() + Send>;
+
+/// Standard I/O redirections cannot be duplicated, so a clone starts out with
+/// inherited stdin, stdout and stderr. Exit hooks are not cloned either.
+impl Clone for FireJailCommand {
+    fn clone(&self) -> Self {
+        FireJailCommand {
+            executable: self.executable.clone(),
+            arg_vec: self.arg_vec.clone(),
+            profile: self.profile.clone(),
+            sorted: self.sorted,
+            warn_on_suspicious_args: self.warn_on_suspicious_args,
+            current_dir: self.current_dir.clone(),
+            env_clear: self.env_clear,
+            envs: self.envs.clone(),
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            on_exit: Vec::new(),
+            kill_on_drop: self.kill_on_drop,
+            consumed: Cell::new(false),
+        }
+    }
+}
+
+/// `flag=path`, keeping the bytes of `path` even if they are not valid UTF-8.
+fn path_arg(flag: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(format!("{}=", flag));
+    arg.push(path);
+    arg
+}
+
+/// Quote `arg` for a POSIX shell, leaving it bare if that is safe.
+fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=,:@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Shows the equivalent shell command line, e.g. `firejail --quiet --noroot -- hostname`.
+impl std::fmt::Debug for FireJailCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("firejail")?;
+        for arg in self.build_args() {
+            write!(f, " {}", shell_quote(&arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// In debug builds, warn about commands which were configured but never spawned.
+/// Inspecting the arguments with `to_args` or `Debug` does not count as spawning.
+#[cfg(debug_assertions)]
+impl Drop for FireJailCommand {
+    fn drop(&mut self) {
+        if self.consumed.get() {
+            return;
+        }
+        let plain = FireJailCommand::with_executable(self.executable.clone());
+        plain.consumed.set(true);
+        if self.build_args() != plain.build_args() {
+            log_warning(&Warning::new(
+                "never-spawned",
+                format!("a configured sandbox for {} was dropped without being spawned", self.executable.to_string_lossy()),
+            ));
+        }
+    }
+}
+
+/// A running sandbox, as returned by `FireJailCommand::spawn`.
+///
+/// Dereferences to the underlying `Child` of the firejail process. Exit hooks registered
+/// with `FireJailCommand::on_exit` run once the child has been waited on; if it is dropped
+/// first, dropping waits for the child so that the hooks never run while it is still alive.
+///
+/// With a `FireJailCommand::timeout`, `wait` and `wait_with_output` kill the sandbox once
+/// the timeout has passed, on top of firejail's own `--timeout`.
+pub struct FireJailChild {
+    child: Option<Child>,
+    on_exit: Vec<ExitHook>,
+    kill_on_drop: bool,
+    deadline: Option<Instant>,
+    timed_out: bool,
+}
+
+/// How long a sandbox killed on drop gets to exit after `SIGTERM` before it is sent `SIGKILL`.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+}
+
+const SIGTERM: i32 = 15;
+
+/// Wait for `child`, terminating it once `deadline` passes. Also returns whether it had to
+/// be terminated.
+fn wait_until(child: &mut Child, deadline: Option<Instant>) -> Result<(ExitStatus, bool)> {
+    let Some(deadline) = deadline else {
+        return Ok((child.wait()?, false));
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            terminate(child);
+            return Ok((child.wait()?, true));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Read `pipe` to the end on a separate thread.
+fn drain<R: std::io::Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+/// Ask `child` to exit with `SIGTERM`, then `SIGKILL` it after `KILL_GRACE_PERIOD`, and reap it.
+fn terminate(child: &mut Child) {
+    if !matches!(child.try_wait(), Ok(None)) {
+        return;
+    }
+    // SAFETY: the child has not been reaped, so its pid cannot have been reused.
+    unsafe { kill(child.id() as i32, SIGTERM) };
+    let deadline = Instant::now() + KILL_GRACE_PERIOD;
+    while Instant::now() < deadline {
+        if !matches!(child.try_wait(), Ok(None)) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+impl FireJailChild {
+    fn run_exit_hooks(&mut self) {
+        for hook in self.on_exit.drain(..) {
+            hook();
+        }
+    }
+
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        let deadline = self.deadline;
+        let waited = wait_until(self.deref_mut(), deadline);
+        self.run_exit_hooks();
+        let (status, timed_out) = waited?;
+        self.timed_out |= timed_out;
+        Ok(status)
+    }
+
+    /// Whether `wait` killed the sandbox because its timeout had passed. A sandbox which
+    /// exited by itself, however late, did not time out.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        let status = self.deref_mut().try_wait();
+        if let Ok(Some(_)) = status {
+            self.run_exit_hooks();
+        }
+        status
+    }
+
+    pub fn wait_with_output(self) -> Result<Output> {
+        self.wait_with_output_timed().map(|(output, _)| output)
+    }
+
+    /// `wait_with_output`, also returning whether the sandbox was killed for timing out.
+    fn wait_with_output_timed(mut self) -> Result<(Output, bool)> {
+        let mut child = self.child.take().expect("child already taken");
+        drop(child.stdin.take());
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+        let waited = wait_until(&mut child, self.deadline);
+        let stdout = stdout.join().expect("stdout reader panicked");
+        let stderr = stderr.join().expect("stderr reader panicked");
+        self.run_exit_hooks();
+        let (status, timed_out) = waited?;
+        Ok((Output { status, stdout: stdout?, stderr: stderr? }, timed_out))
+    }
+}
+
+/// Resource usage of a running sandbox, see `FireJailChild::stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SandboxStats {
+    /// User plus system CPU time consumed so far.
+    pub cpu_time: Duration,
+    /// Resident memory in bytes.
+    pub rss_bytes: u64,
+    /// Number of processes, including firejail itself.
+    pub num_procs: usize,
 }
 
+/// `/proc` reports CPU times in units of USER_HZ, which Linux fixes at 100 per second.
+const USER_HZ: u64 = 100;
+
+struct ProcStat {
+    pid: u32,
+    ppid: u32,
+    ticks: u64,
+}
+
+fn read_proc_stat(pid: u32) -> Result<ProcStat> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // The command name is parenthesized and may contain spaces, so split after it.
+    let fields: Vec<_> = stat.rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+    let field = |i: usize| fields.get(i)
+        .and_then(|x| x.parse::<u64>().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("malformed /proc/{}/stat", pid)));
+    Ok(ProcStat { pid, ppid: field(1)? as u32, ticks: field(11)? + field(12)? })
+}
+
+fn read_rss_bytes(pid: u32) -> u64 {
+    std::fs::read_to_string(format!("/proc/{}/status", pid))
+        .ok()
+        .and_then(|status| status.lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|x| x.trim().trim_end_matches("kB").trim().parse::<u64>().ok()))
+        .map(|kb| kb * 1024)
+        .unwrap_or(0)
+}
+
+/// Parse a kernel CPU list such as `0-3,8,10-11`.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|x| !x.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        cpus.extend(first.parse::<usize>().ok()?..=last.parse::<usize>().ok()?);
+    }
+    Some(cpus)
+}
+
+/// The CPUs this process may run on, from `Cpus_allowed_list` in `/proc/self/status`.
+fn allowed_cpus() -> Option<Vec<usize>> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .and_then(parse_cpu_list)
+}
+
+impl FireJailChild {
+    /// Report the resource usage of the firejail process and everything running under it.
+    ///
+    /// Fails with `ErrorKind::NotFound` once the sandbox is gone.
+    pub fn stats(&self) -> Result<SandboxStats> {
+        let root = read_proc_stat(self.id())?;
+        let others: Vec<_> = std::fs::read_dir("/proc")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|&pid| pid != root.pid)
+            .filter_map(|pid| read_proc_stat(pid).ok())
+            .collect();
+        let mut tree = vec![root];
+        let mut i = 0;
+        while i < tree.len() {
+            let parent = tree[i].pid;
+            tree.extend(others.iter()
+                .filter(|x| x.ppid == parent)
+                .map(|x| ProcStat { pid: x.pid, ppid: x.ppid, ticks: x.ticks }));
+            i += 1;
+        }
+        Ok(SandboxStats {
+            cpu_time: Duration::from_millis(tree.iter().map(|x| x.ticks).sum::<u64>() * 1000 / USER_HZ),
+            rss_bytes: tree.iter().map(|x| read_rss_bytes(x.pid)).sum(),
+            num_procs: tree.len(),
+        })
+    }
+}
+
+impl Deref for FireJailChild {
+    type Target = Child;
+    fn deref(&self) -> &Child {
+        self.child.as_ref().expect("child already taken")
+    }
+}
+
+impl DerefMut for FireJailChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        self.child.as_mut().expect("child already taken")
+    }
+}
+
+impl Drop for FireJailChild {
+    fn drop(&mut self) {
+        if self.kill_on_drop {
+            if let Some(child) = self.child.as_mut() {
+                terminate(child);
+            }
+        }
+        if self.on_exit.is_empty() {
+            return;
+        }
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.wait();
+        }
+        self.run_exit_hooks();
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum CapsDrop {
     NotSpecified,
     DropAll,
@@ -49,6 +389,69 @@ pub enum CapsDrop {
     },
 }
 
+macro_rules! capabilities {
+    ($($variant: ident => $name: expr,)*) => {
+        /// A Linux capability, for `CapsDropBuilder::drop` and `CapsDropBuilder::keep`.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum Capability {
+            $($variant,)*
+        }
+
+        impl Capability {
+            /// The name firejail expects, i.e. lowercase without the `CAP_` prefix.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Capability::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+capabilities! {
+    Chown => "chown",
+    DacOverride => "dac_override",
+    DacReadSearch => "dac_read_search",
+    Fowner => "fowner",
+    Fsetid => "fsetid",
+    Kill => "kill",
+    Setgid => "setgid",
+    Setuid => "setuid",
+    Setpcap => "setpcap",
+    LinuxImmutable => "linux_immutable",
+    NetBindService => "net_bind_service",
+    NetBroadcast => "net_broadcast",
+    NetAdmin => "net_admin",
+    NetRaw => "net_raw",
+    IpcLock => "ipc_lock",
+    IpcOwner => "ipc_owner",
+    SysModule => "sys_module",
+    SysRawio => "sys_rawio",
+    SysChroot => "sys_chroot",
+    SysPtrace => "sys_ptrace",
+    SysPacct => "sys_pacct",
+    SysAdmin => "sys_admin",
+    SysBoot => "sys_boot",
+    SysNice => "sys_nice",
+    SysResource => "sys_resource",
+    SysTime => "sys_time",
+    SysTtyConfig => "sys_tty_config",
+    Mknod => "mknod",
+    Lease => "lease",
+    AuditWrite => "audit_write",
+    AuditControl => "audit_control",
+    Setfcap => "setfcap",
+    MacOverride => "mac_override",
+    MacAdmin => "mac_admin",
+    Syslog => "syslog",
+    WakeAlarm => "wake_alarm",
+    BlockSuspend => "block_suspend",
+    AuditRead => "audit_read",
+    Perfmon => "perfmon",
+    Bpf => "bpf",
+    CheckpointRestore => "checkpoint_restore",
+}
+
 pub struct CapsDropBuilder(Vec<InlinableString>, Vec<InlinableString>);
 
 impl CapsDrop {
@@ -65,18 +468,32 @@ impl CapsDropBuilder {
         self.1.push(InlinableString::from(a.as_ref()));
         self
     }
-    pub fn whilelist<A: AsRef<str>>(&mut self, a: A) -> &mut Self {
+    pub fn whitelist<A: AsRef<str>>(&mut self, a: A) -> &mut Self {
         self.0.push(InlinableString::from(a.as_ref()));
         self
     }
+    #[deprecated(note = "use whitelist")]
+    pub fn whilelist<A: AsRef<str>>(&mut self, a: A) -> &mut Self {
+        self.whitelist(a)
+    }
     pub fn blacklists<A: AsRef<str>, I: IntoIterator<Item=A>>(&mut self, i: I) -> &mut Self {
         self.1.extend(i.into_iter().map(|x| InlinableString::from(x.as_ref())));
         self
     }
-    pub fn whilelists<A: AsRef<str>, I: IntoIterator<Item=A>>(&mut self, i: I) -> &mut Self {
+    pub fn whitelists<A: AsRef<str>, I: IntoIterator<Item=A>>(&mut self, i: I) -> &mut Self {
         self.0.extend(i.into_iter().map(|x| InlinableString::from(x.as_ref())));
         self
     }
+    #[deprecated(note = "use whitelists")]
+    pub fn whilelists<A: AsRef<str>, I: IntoIterator<Item=A>>(&mut self, i: I) -> &mut Self {
+        self.whitelists(i)
+    }
+    pub fn drop(&mut self, cap: Capability) -> &mut Self {
+        self.blacklist(cap.name())
+    }
+    pub fn keep(&mut self, cap: Capability) -> &mut Self {
+        self.whitelist(cap.name())
+    }
     pub fn build(&self) -> CapsDrop {
         CapsDrop::Settings {
             whitelist: self.0.clone(),
@@ -85,12 +502,22 @@ impl CapsDropBuilder {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum IpConfig {
     NotSpecified,
     Address(InlinableString),
+    /// Two IPv4 addresses separated by `,` or `-`, e.g. `10.10.20.50-10.10.20.99`.
     AddressRange(InlinableString)
 }
 
+/// The bounds of an `IpConfig::AddressRange`, if it is well-formed.
+fn parse_ip_range(range: &str) -> Option<(std::net::Ipv4Addr, std::net::Ipv4Addr)> {
+    let (start, end) = range.split_once(',').or_else(|| range.split_once('-'))?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    if start <= end { Some((start, end)) } else { None }
+}
+
+#[derive(Clone, Debug)]
 pub struct InterfaceConfig {
     default_gw: Option<InlinableString>,
     mac: Option<InlinableString>,
@@ -101,43 +528,182 @@ pub struct InterfaceConfig {
     veth_name: Option<InlinableString>
 }
 
+pub struct InterfaceConfigBuilder(InterfaceConfig);
+
+impl InterfaceConfigBuilder {
+    pub fn default_gw<S: AsRef<str>>(&mut self, gw: S) -> &mut Self {
+        self.0.default_gw = Some(InlinableString::from(gw.as_ref()));
+        self
+    }
+    pub fn mac<S: AsRef<str>>(&mut self, mac: S) -> &mut Self {
+        self.0.mac = Some(InlinableString::from(mac.as_ref()));
+        self
+    }
+    /// A fixed IPv4 address, `--ip`. Replaces an earlier `ip_range`.
+    pub fn ip<S: AsRef<str>>(&mut self, ip: S) -> &mut Self {
+        self.0.ip_config = IpConfig::Address(InlinableString::from(ip.as_ref()));
+        self
+    }
+    /// Pick an IPv4 address from `start` to `end`, `--iprange`. Replaces an earlier `ip`.
+    pub fn ip_range<S: AsRef<str>, E: AsRef<str>>(&mut self, start: S, end: E) -> &mut Self {
+        self.0.ip_config = IpConfig::AddressRange(InlinableString::from(format!("{},{}", start.as_ref(), end.as_ref())));
+        self
+    }
+    pub fn ip6<S: AsRef<str>>(&mut self, ip: S) -> &mut Self {
+        self.0.ip6 = Some(InlinableString::from(ip.as_ref()));
+        self
+    }
+    pub fn mtu(&mut self, mtu: usize) -> &mut Self {
+        self.0.mtu = Some(mtu);
+        self
+    }
+    pub fn netmask<S: AsRef<str>>(&mut self, netmask: S) -> &mut Self {
+        self.0.netmask = Some(InlinableString::from(netmask.as_ref()));
+        self
+    }
+    pub fn veth_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.0.veth_name = Some(InlinableString::from(name.as_ref()));
+        self
+    }
+    pub fn build(&self) -> InterfaceConfig {
+        self.0.clone()
+    }
+}
+
+/// The network namespace of the sandbox, emitted as `--net`.
+#[derive(Clone, Debug)]
 pub enum Net {
-    NotSpecfied,
+    NotSpecified,
+    /// `--net=none`: only a loopback interface.
     None,
+    /// `--net=<name>`: a new namespace connected to the host bridge or interface `name`.
     Interfaces((InlinableString, Vec<InterfaceConfig>)),
+    /// The old, misspelled name of `NotSpecified`, which `net` treats the same.
+    #[deprecated(note = "use Net::NotSpecified")]
+    NotSpecfied,
+}
+
+impl Net {
+    /// Whether a network was configured, treating `NotSpecfied` like `NotSpecified`.
+    #[allow(deprecated)]
+    fn is_specified(&self) -> bool {
+        !matches!(self, Net::NotSpecified | Net::NotSpecfied)
+    }
 }
 
+#[derive(Clone, Debug)]
 pub enum NetFilter {
     Disable,
+    /// firejail's default client filter.
     Default,
+    /// The iptables rules in `path`, with `$ARG1`, `$ARG2`, ... replaced by `args`.
     WithSetting {
         path: PathBuf,
         args: Option<Vec<InlinableString>>
     }
 }
 
+impl NetFilter {
+    fn to_arg(&self, flag: &str) -> Option<OsString> {
+        match self {
+            NetFilter::Disable => None,
+            NetFilter::Default => Some(flag.into()),
+            NetFilter::WithSetting { path, args } => {
+                let mut arg = path_arg(flag, path);
+                for x in args.iter().flatten() {
+                    arg.push(",");
+                    arg.push(x.as_ref());
+                }
+                Some(arg)
+            }
+        }
+    }
+
+    fn check(&self, flag: &str) -> FireJailResult<()> {
+        match self {
+            NetFilter::WithSetting { path, .. } if !path.exists() => Err(FireJailError::InvalidProfile(
+                format!("{} file {} does not exist", flag, path.display()))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Join {
     Pid(usize),
     Name(InlinableString)
 }
 
+impl std::fmt::Display for Join {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Join::Pid(pid) => write!(f, "{}", pid),
+            Join::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Overlay {
     NoSpecified,
+    /// `--overlay`: changes persist in an overlay under `~/.firejail`, unique to this sandbox.
+    Default,
+    /// `--overlay-tmp`: changes live in a tmpfs and are discarded on exit.
     Tmp,
+    /// `--overlay-named=<name>`: changes persist in an overlay reused by name.
     Named(InlinableString),
 }
 
+#[derive(Clone, Debug)]
 pub enum Private {
     NoSpecified,
     Default,
     Directory(PathBuf),
 }
 
+#[derive(Clone, Debug)]
 pub enum PrivateList {
     NoSpecified,
     Empty,
     Files(Vec<PathBuf>),
 }
+impl PrivateList {
+    fn extend<I, P: AsRef<Path>>(&mut self, entries: I) where
+        I: IntoIterator<Item=P>
+    {
+        let entries = entries.into_iter().map(|x| x.as_ref().to_path_buf());
+        match self {
+            PrivateList::Files(files) => files.extend(entries),
+            other => {
+                let files: Vec<_> = entries.collect();
+                *other = if files.is_empty() { PrivateList::Empty } else { PrivateList::Files(files) };
+            }
+        }
+    }
+
+    fn to_arg(&self, flag: &str, sorted: bool) -> Option<OsString> {
+        match self {
+            PrivateList::NoSpecified => None,
+            PrivateList::Empty => Some(flag.into()),
+            PrivateList::Files(files) => {
+                let mut files: Vec<_> = files.iter().collect();
+                if sorted {
+                    files.sort();
+                }
+                let mut arg = OsString::from(format!("{}=", flag));
+                for (i, file) in files.iter().enumerate() {
+                    if i > 0 {
+                        arg.push(",");
+                    }
+                    arg.push(file);
+                }
+                Some(arg)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Seccomp {
     NotSpecified,
     Enable,
@@ -147,12 +713,14 @@ pub enum Seccomp {
     Keep(Vec<InlinableString>),
 }
 
+#[derive(Clone, Debug)]
 pub enum Shell {
     NotSpecified,
     SetToNone,
     SetTo(PathBuf)
 }
 
+#[derive(Clone, Debug)]
 pub enum X11 {
     NotSpecified,
     Auto,
@@ -163,458 +731,4488 @@ pub enum X11 {
     Xvfb,
 }
 
+/// A wall-clock limit for `--timeout`, in hours, minutes and seconds.
+#[derive(Clone, Debug)]
 pub struct Timeout(usize, usize, usize);
 
-struct Profile {
-    verbose: bool,
-    allow_debuggers: bool,
-    allusers: bool,
-    apparmor: bool,
-    appimage: bool,
-    caps: bool,
-    caps_drop: CapsDrop,
-    bind: Vec<(PathBuf, PathBuf)>,
-    blacklists: Vec<PathBuf>,
-    cgroup: Option<InlinableString>,
-    cpu: Vec<usize>,
-    disable_mnt: bool,
-    deterministic_exit_code: bool,
-    dns: Vec<InlinableString>,
-    hostname: Option<InlinableString>,
-    hosts_file: Option<PathBuf>,
-    ignore: Vec<InlinableString>,
-    interface: Vec<InlinableString>,
-    default_net: InterfaceConfig,
-    networks: Net,
-    ipc_namespace: bool,
-    keep_dev_shm: bool,
-    keep_var_tmp: bool,
-    machine_id: bool,
-    memory_deny_write_execute: bool,
-    name: Option<InlinableString>,
-    netfilter: NetFilter,
-    netfilter6: NetFilter,
-    join: Option<Join>,
-    join_network: Option<Join>,
-    join_fs: Option<Join>,
-    join_or_start: Option<InlinableString>,
-    netns: Option<InlinableString>,
-    nice: Option<usize>,
-    no3d: bool,
-    noautopulse: bool,
-    noblacklist: Vec<PathBuf>,
-    nodbus: bool,
-    nodvd: bool,
-    noexec: Vec<PathBuf>,
-    nogroups: bool,
-    nonewprivs: bool,
-    noprofile: bool,
-    noroot: bool,
-    nosound: bool,
-    notv: bool,
-    nou2f: bool,
-    novideo: bool,
-    nowhitelist: Vec<PathBuf>,
-    output: Option<PathBuf>,
-    output_stderr: Option<PathBuf>,
-    overlay: Overlay,
-    private: Private,
-    private_bin: PrivateList,
-    private_cache: bool,
-    private_cwd: Private,
-    private_dev: bool,
-    private_etc: PrivateList,
-    private_home: PrivateList,
-    private_lib: PrivateList,
-    private_opt: PrivateList,
-    private_srv: PrivateList,
-    private_tmp: bool,
-    profile: Option<PathBuf>,
-    protocal: Vec<InlinableString>,
-    read_only: Vec<PathBuf>,
-    read_write: Vec<PathBuf>,
-    rlimit: Option<usize>,
-    rlimit_cpu: Option<usize>,
-    rlimit_fsize: Option<usize>,
-    rlimit_nofile: Option<usize>,
-    rlimit_nproc: Option<usize>,
-    rlimit_sigpending: Option<usize>,
-    remove_env: Vec<InlinableString>,
-    seccomp: Seccomp,
-    shell: Shell,
-    timeout: Option<Timeout>,
-    tmpfs: Vec<PathBuf>,
-    tunnel: Option<InlinableString>,
-    whitelist: Vec<PathBuf>,
-    writable_etc: bool,
-    writable_run_user: bool,
-    writable_var: bool,
-    writable_var_log: bool,
-    x11: X11
+impl Timeout {
+    pub fn new(hours: usize, minutes: usize, seconds: usize) -> Self {
+        Timeout(hours, minutes, seconds)
+    }
+    pub fn from_secs(secs: usize) -> Self {
+        Timeout(secs / 3600, secs % 3600 / 60, secs % 60)
+    }
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_secs((self.0 * 3600 + self.1 * 60 + self.2) as u64)
+    }
 }
 
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.0, self.1, self.2)
+    }
+}
 
-impl FireJailCommand {
-    bool_option!(verbose);
-    bool_option!(allow_debuggers);
-    bool_option!(allusers);
-    bool_option!(apparmor);
+/// The result of `FireJailCommand::spawn_checked`.
+#[derive(Clone, Debug)]
+pub struct SandboxOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Whether the sandbox was killed because its timeout expired.
+    pub timed_out: bool,
+}
+
+/// A set of socket protocol families for `--protocol`, combined with `|`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Protocol(u8);
+
+impl Protocol {
+    pub const UNIX: Protocol = Protocol(1);
+    pub const INET: Protocol = Protocol(1 << 1);
+    pub const INET6: Protocol = Protocol(1 << 2);
+    pub const NETLINK: Protocol = Protocol(1 << 3);
+    pub const PACKET: Protocol = Protocol(1 << 4);
+    pub const BLUETOOTH: Protocol = Protocol(1 << 5);
+
+    pub fn empty() -> Self {
+        Protocol(0)
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    pub fn contains(self, other: Protocol) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// The firejail names of the families in this set, in canonical order.
+    pub fn names(self) -> impl Iterator<Item=&'static str> {
+        PROTOCOL_NAMES.iter()
+            .filter(move |(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl std::ops::BitOr for Protocol {
+    type Output = Protocol;
+    fn bitor(self, rhs: Protocol) -> Protocol {
+        Protocol(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Protocol {
+    fn bitor_assign(&mut self, rhs: Protocol) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Every protocol family firejail's `--protocol` accepts, in the order they are emitted.
+const PROTOCOL_NAMES: &[(Protocol, &str)] = &[
+    (Protocol::UNIX, "unix"),
+    (Protocol::INET, "inet"),
+    (Protocol::INET6, "inet6"),
+    (Protocol::NETLINK, "netlink"),
+    (Protocol::PACKET, "packet"),
+    (Protocol::BLUETOOTH, "bluetooth"),
+];
+
+/// The number of `--dns` servers firejail honors.
+const MAX_DNS_SERVERS: usize = 3;
+
+/// Deduplicate protocol names, putting known families first in canonical order.
+fn canonical_protocols(names: &[InlinableString]) -> Vec<&str> {
+    let mut result: Vec<&str> = PROTOCOL_NAMES.iter()
+        .map(|(_, name)| *name)
+        .filter(|name| names.iter().any(|x| x == name))
+        .collect();
+    for name in names {
+        if !result.contains(&name.as_ref()) {
+            result.push(name);
+        }
+    }
+    result
+}
+
+/// Devices which can be kept visible under `--private-dev`.
+///
+/// firejail keeps the GPU, sound, video, DVD, TV and U2F devices in its private `/dev`
+/// unless told otherwise; `/dev/shm` has a dedicated `--keep-dev-shm` flag, available
+/// since firejail 0.9.66.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Device {
+    Shm,
+    Dri,
+    Sound,
+    Video,
+    Dvd,
+    Tv,
+    U2f,
+}
+
+/// The `--rlimit-nproc` set by `FireJailCommand::hardened`: enough for build tools and shells,
+/// low enough to stop a fork bomb.
+pub const HARDENED_NPROC: usize = 256;
+
+/// The system directories `FireJailCommand::immutable_root` makes read-only.
+pub const IMMUTABLE_ROOT_READ_ONLY: &[&str] = &["/usr", "/bin", "/lib", "/etc"];
+
+/// The scratch directories `FireJailCommand::immutable_root` replaces with a tmpfs.
+pub const IMMUTABLE_ROOT_SCRATCH: &[&str] = &["/tmp", "/var/tmp"];
+
+/// Resource limits applied in one go by `FireJailCommand::resource_limited`.
+///
+/// Unset fields leave the corresponding option of the command untouched.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceConfig {
+    /// CPUs the sandbox may run on (`--cpu`).
+    pub cpus: Vec<usize>,
+    /// CPU time in seconds (`--rlimit-cpu`).
+    pub cpu_time: Option<usize>,
+    /// Address space size in bytes (`--rlimit-as`).
+    pub memory: Option<usize>,
+    /// Number of processes (`--rlimit-nproc`).
+    pub processes: Option<usize>,
+    /// Number of open file descriptors (`--rlimit-nofile`).
+    pub open_files: Option<usize>,
+    /// Tasks file of an existing cgroup to join (`--cgroup`).
+    pub cgroup: Option<InlinableString>,
+}
+
+/// Every `--rlimit-*` option, set in one go by `FireJailCommand::rlimits`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rlimits {
+    /// Address space size in bytes (`--rlimit-as`).
+    pub address_space: Option<usize>,
+    /// CPU time in seconds (`--rlimit-cpu`).
+    pub cpu: Option<usize>,
+    /// Size of created files in bytes (`--rlimit-fsize`).
+    pub fsize: Option<usize>,
+    /// Number of open file descriptors (`--rlimit-nofile`).
+    pub nofile: Option<usize>,
+    /// Number of processes (`--rlimit-nproc`).
+    pub nproc: Option<usize>,
+    /// Number of pending signals (`--rlimit-sigpending`).
+    pub sigpending: Option<usize>,
+}
+
+/// How `stdout_file` and `stderr_file` open an existing file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileMode {
+    Truncate,
+    Append,
+}
+
+#[derive(Debug)]
+pub enum FireJailError {
+    /// No template was registered under this name.
+    UnknownTemplate(String),
+    /// The `firejail` binary was not found.
+    NotInstalled,
+    /// firejail could not be started, or waiting for it failed.
+    Spawn(std::io::Error),
+    /// firejail itself failed with this exit code and stderr, before or while setting up
+    /// the sandbox.
+    Firejail { code: i32, stderr: String },
+    /// firejail lacks the privileges to set up the sandbox; carries its stderr.
+    InsufficientPrivileges(String),
+    /// A profile file could not be read.
+    Io(std::io::Error),
+    /// A profile contains a malformed directive.
+    InvalidProfile(String),
+    /// Options which cannot be used together.
+    ConflictingOptions(String),
+    /// `firejail --version` printed something other than a version; carries its stdout.
+    UnrecognizedVersion(String),
+    /// The rendered arguments, of this many bytes, would exceed the kernel's `ARG_MAX`.
+    ArgumentListTooLong(usize),
+    /// A size string `parse_size` could not read.
+    InvalidSize(String),
+}
+
+impl std::fmt::Display for FireJailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FireJailError::UnknownTemplate(name) => write!(f, "unknown profile template: {}", name),
+            FireJailError::NotInstalled => write!(f, "firejail is not installed or not in PATH"),
+            FireJailError::Spawn(e) => write!(f, "failed to spawn firejail: {}", e),
+            FireJailError::Firejail { code, stderr } => write!(f, "firejail exited with {}: {}", code, stderr.trim()),
+            FireJailError::InsufficientPrivileges(stderr) =>
+                write!(f, "firejail needs to be setuid root or have the required capabilities: {}", stderr.trim()),
+            FireJailError::Io(e) => write!(f, "failed to read profile: {}", e),
+            FireJailError::InvalidProfile(msg) => write!(f, "invalid profile: {}", msg),
+            FireJailError::ConflictingOptions(msg) => write!(f, "conflicting options: {}", msg),
+            FireJailError::UnrecognizedVersion(output) =>
+                write!(f, "unrecognized firejail --version output: {}", output.trim()),
+            FireJailError::ArgumentListTooLong(size) =>
+                write!(f, "argument list of {} bytes is too long, consider moving options into a profile file", size),
+            FireJailError::InvalidSize(size) => write!(f, "invalid size {:?}, expected bytes or a K, M or G suffix", size),
+        }
+    }
+}
+
+impl std::error::Error for FireJailError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FireJailError::Spawn(e) | FireJailError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The result of operations which can fail with a `FireJailError`.
+pub type FireJailResult<T> = std::result::Result<T, FireJailError>;
+
+/// Treats any I/O error as a failure to run firejail.
+impl From<std::io::Error> for FireJailError {
+    fn from(e: std::io::Error) -> Self {
+        FireJailError::Spawn(e)
+    }
+}
+
+/// Classify an error from starting `firejail`; `NotFound` means it is not installed.
+fn spawn_error(e: std::io::Error) -> FireJailError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => FireJailError::NotInstalled,
+        _ => FireJailError::Spawn(e),
+    }
+}
+
+/// The budget for firejail's arguments: three quarters of the usual 2 MiB `ARG_MAX`,
+/// leaving the rest for the environment.
+const ARG_BYTES_LIMIT: usize = 3 * 512 * 1024;
+
+/// The kernel's `MAX_ARG_STRLEN`, the limit for any single argument.
+const ARG_STRLEN_LIMIT: usize = 128 * 1024;
+
+/// The features `firejail --version` lists as disabled, e.g. `AppArmor` for
+/// `- AppArmor support is disabled`.
+fn disabled_features(version_output: &str) -> Vec<String> {
+    version_output.lines()
+        .filter_map(|line| line.trim().trim_start_matches('-').trim().strip_suffix(" support is disabled"))
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// The `firejail --version` output of the installed firejail, or `None` if it is not
+/// installed. firejail runs once per process; the result is cached.
+fn installed_version_output() -> Option<&'static str> {
+    static OUTPUT: OnceLock<Option<String>> = OnceLock::new();
+    OUTPUT.get_or_init(|| {
+        let output = Command::new("firejail").arg("--version").output().ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }).as_deref()
+}
+
+/// The firejail release which introduced `--keep-dev-shm`.
+const KEEP_DEV_SHM_VERSION: (u32, u32, u32) = (0, 9, 66);
+
+/// Parse a size such as `4096`, `64K`, `10M` or `1G` into bytes. Suffixes are binary
+/// (`1K` is 1024 bytes) and case-insensitive, as in firejail's own profiles.
+pub fn parse_size(size: &str) -> FireJailResult<usize> {
+    let invalid = || FireJailError::InvalidSize(size.to_string());
+    let trimmed = size.trim();
+    let (digits, shift) = match trimmed.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 30),
+        _ => (trimmed, 0),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let n: usize = digits.parse().map_err(|_| invalid())?;
+    n.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// The `X.Y.Z` of the `firejail version X.Y.Z` line in `firejail --version` output.
+fn parse_version(version_output: &str) -> Option<(u32, u32, u32)> {
+    let version = version_output.lines()
+        .find_map(|line| line.trim().strip_prefix("firejail version "))?;
+    // Release candidates look like `0.9.73-rc1`; only the leading digits of a part count.
+    let mut parts = version.trim().splitn(3, '.').map(|part| {
+        let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+        part[..digits].parse::<u32>().ok()
+    });
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// The version of the installed firejail, e.g. `(0, 9, 72)`.
+pub fn firejail_version() -> FireJailResult<(u32, u32, u32)> {
+    let output = Command::new("firejail").arg("--version").output().map_err(spawn_error)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version(&stdout).ok_or_else(|| FireJailError::UnrecognizedVersion(stdout.into_owned()))
+}
+
+/// The file names of the shared libraries listed in `ldd` output.
+fn ldd_libraries(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let path = match line.split_once("=>") {
+                Some((_, target)) => target.trim(),
+                None => line,
+            };
+            path.split_whitespace().next().filter(|x| x.starts_with('/'))
+        })
+        .filter_map(|path| Path::new(path).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// The exit code of firejail when it fails to set up a sandbox.
+const FIREJAIL_ERROR_CODE: i32 = 1;
+
+/// The prefix of every error message firejail prints about itself.
+const FIREJAIL_ERROR_PREFIX: &str = "Error: ";
+
+/// Recognize failures of firejail itself, as opposed to the sandboxed program, from its
+/// stderr and exit code: firejail exits with `FIREJAIL_ERROR_CODE` after printing an
+/// `Error: ` line first. Anything else, such as `error:` from a compiler or `Error` without
+/// the colon, is left to the program.
+fn classify_failure(stderr: &str, code: Option<i32>) -> Option<FireJailError> {
+    const PRIVILEGE_ERRORS: &[&str] = &[
+        "need to be root",
+        "must be root",
+        "setuid",
+        "permission denied",
+        "operation not permitted",
+    ];
+    if code != Some(FIREJAIL_ERROR_CODE) {
+        return None;
+    }
+    let message = stderr.lines()
+        .find(|line| !line.trim().is_empty())?
+        .strip_prefix(FIREJAIL_ERROR_PREFIX)?
+        .to_lowercase();
+    if PRIVILEGE_ERRORS.iter().any(|x| message.contains(x)) {
+        Some(FireJailError::InsufficientPrivileges(stderr.to_string()))
+    } else {
+        Some(FireJailError::Firejail { code: FIREJAIL_ERROR_CODE, stderr: stderr.to_string() })
+    }
+}
+
+/// A non-fatal remark about a sandbox configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Warning {
+    fn new<S: Into<String>>(code: &'static str, message: S) -> Self {
+        Warning { code, message: message.into() }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+type WarningHook = Box<dyn Fn(&Warning) + Send + Sync>;
+
+static WARNING_HOOK: RwLock<Option<WarningHook>> = RwLock::new(None);
+
+/// Route warnings raised while running commands to `hook` instead of stderr.
+pub fn set_warning_hook<F: Fn(&Warning) + Send + Sync + 'static>(hook: F) {
+    WARNING_HOOK.write().unwrap_or_else(|e| e.into_inner()).replace(Box::new(hook));
+}
+
+fn log_warning(warning: &Warning) {
+    match WARNING_HOOK.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(hook) => hook(warning),
+        None => eprintln!("firejail-spawn: {}", warning),
+    }
+}
+
+/// Flags which, passed as program arguments, were most likely meant for firejail.
+const FIREJAIL_FLAGS: &[&str] = &[
+    "--allusers", "--apparmor", "--appimage", "--bind", "--blacklist", "--caps", "--caps.drop",
+    "--caps.keep", "--cgroup", "--cpu", "--deterministic-exit-code", "--disable-mnt", "--dns",
+    "--hostname", "--hosts-file", "--ignore", "--interface", "--join", "--keep-dev-shm",
+    "--name", "--net", "--nice", "--noblacklist", "--noexec", "--noprofile", "--noroot",
+    "--nonewprivs", "--nowhitelist", "--private", "--private-cache", "--private-dev",
+    "--private-etc", "--private-tmp", "--profile", "--protocol", "--quiet", "--read-only",
+    "--read-write", "--rlimit-as", "--rlimit-cpu", "--rlimit-nofile", "--rlimit-nproc",
+    "--seccomp", "--tmpfs", "--whitelist",
+];
+
+/// Entries of `/etc` which virtually every dynamically linked program needs.
+const PRIVATE_ETC_BASE: &[&str] = &[
+    "group",
+    "ld.so.cache",
+    "ld.so.conf",
+    "ld.so.conf.d",
+    "ld.so.preload",
+    "localtime",
+    "nsswitch.conf",
+    "passwd",
+];
+
+/// Curated `/etc` requirements of common tools, on top of `PRIVATE_ETC_BASE`.
+const PRIVATE_ETC_TABLE: &[(&str, &[&str])] = &[
+    ("curl", &["ca-certificates", "crypto-policies", "host.conf", "hosts", "pki", "resolv.conf", "ssl"]),
+    ("wget", &["ca-certificates", "crypto-policies", "host.conf", "hosts", "pki", "resolv.conf", "ssl", "wgetrc"]),
+    ("git", &["ca-certificates", "crypto-policies", "gitconfig", "hosts", "pki", "resolv.conf", "ssh", "ssl"]),
+    ("ssh", &["crypto-policies", "hosts", "resolv.conf", "ssh"]),
+    ("scp", &["crypto-policies", "hosts", "resolv.conf", "ssh"]),
+    ("python", &["mime.types", "ssl", "ca-certificates"]),
+    ("python3", &["mime.types", "ssl", "ca-certificates"]),
+    ("node", &["ca-certificates", "hosts", "resolv.conf", "ssl"]),
+    ("gcc", &["alternatives"]),
+    ("bash", &["bash.bashrc", "bashrc", "inputrc", "profile", "profile.d"]),
+    ("sh", &["profile"]),
+    ("vim", &["vimrc", "vim"]),
+    ("less", &["lesskey"]),
+    ("firefox", &["alternatives", "ca-certificates", "crypto-policies", "fonts", "host.conf", "hostname",
+                  "hosts", "machine-id", "mime.types", "pki", "pulse", "resolv.conf", "ssl"]),
+];
+
+/// The sandbox configuration of a `FireJailCommand`, independent of the program it runs.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    verbose: bool,
+    allow_debuggers: bool,
+    allusers: bool,
+    apparmor: bool,
+    appimage: bool,
+    caps: bool,
+    caps_drop: CapsDrop,
+    bind: Vec<(PathBuf, PathBuf)>,
+    blacklists: Vec<PathBuf>,
+    cgroup: Option<InlinableString>,
+    cpu: Vec<usize>,
+    disable_mnt: bool,
+    deterministic_exit_code: bool,
+    dns: Vec<InlinableString>,
+    hostname: Option<InlinableString>,
+    hosts_file: Option<PathBuf>,
+    ignore: Vec<InlinableString>,
+    interface: Vec<InlinableString>,
+    default_net: InterfaceConfig,
+    networks: Net,
+    ipc_namespace: bool,
+    keep_dev_shm: bool,
+    keep_var_tmp: bool,
+    machine_id: bool,
+    memory_deny_write_execute: bool,
+    name: Option<InlinableString>,
+    netfilter: NetFilter,
+    netfilter6: NetFilter,
+    join: Option<Join>,
+    join_network: Option<Join>,
+    join_fs: Option<Join>,
+    join_or_start: Option<InlinableString>,
+    netns: Option<InlinableString>,
+    nice: Option<usize>,
+    no3d: bool,
+    noautopulse: bool,
+    noblacklist: Vec<PathBuf>,
+    nodbus: bool,
+    nodvd: bool,
+    noexec: Vec<PathBuf>,
+    noexec_writable: bool,
+    nogroups: bool,
+    nonewprivs: bool,
+    noprofile: bool,
+    noroot: bool,
+    nosound: bool,
+    notv: bool,
+    nou2f: bool,
+    novideo: bool,
+    nowhitelist: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    output_stderr: Option<PathBuf>,
+    overlay: Overlay,
+    private: Private,
+    private_bin: PrivateList,
+    private_cache: bool,
+    private_cwd: Private,
+    private_dev: bool,
+    private_etc: PrivateList,
+    private_home: PrivateList,
+    private_lib: PrivateList,
+    private_opt: PrivateList,
+    private_srv: PrivateList,
+    private_tmp: bool,
+    profile: Option<PathBuf>,
+    protocol: Vec<InlinableString>,
+    read_only: Vec<PathBuf>,
+    read_write: Vec<PathBuf>,
+    rlimit: Option<usize>,
+    rlimit_cpu: Option<usize>,
+    rlimit_fsize: Option<usize>,
+    rlimit_nofile: Option<usize>,
+    rlimit_nproc: Option<usize>,
+    rlimit_sigpending: Option<usize>,
+    remove_env: Vec<InlinableString>,
+    seccomp: Seccomp,
+    shell: Shell,
+    timeout: Option<Timeout>,
+    tmpfs: Vec<PathBuf>,
+    tunnel: Option<InlinableString>,
+    whitelist: Vec<PathBuf>,
+    writable_etc: bool,
+    writable_run_user: bool,
+    writable_var: bool,
+    writable_var_log: bool,
+    x11: X11,
+    sandbox_env: Vec<(InlinableString, InlinableString)>,
+    raw_args: Vec<InlinableString>,
+}
+
+
+impl Default for InterfaceConfig {
+    fn default() -> Self {
+        InterfaceConfig {
+            default_gw: None,
+            mac: None,
+            ip_config: IpConfig::NotSpecified,
+            ip6: None,
+            mtu: None,
+            netmask: None,
+            veth_name: None
+        }
+    }
+}
+
+impl InterfaceConfig {
+    pub fn builder() -> InterfaceConfigBuilder {
+        InterfaceConfigBuilder(InterfaceConfig::default())
+    }
+
+    /// The flags configuring the interface of the preceding `--net`.
+    fn to_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        if let Some(gw) = &self.default_gw {
+            args.push(format!("--defaultgw={}", gw).into());
+        }
+        if let Some(mac) = &self.mac {
+            args.push(format!("--mac={}", mac).into());
+        }
+        match &self.ip_config {
+            IpConfig::Address(ip) => { args.push(format!("--ip={}", ip).into()); }
+            IpConfig::AddressRange(range) => {
+                let range = parse_ip_range(range).map_or_else(|| range.to_string(), |(start, end)| format!("{},{}", start, end));
+                args.push(format!("--iprange={}", range).into());
+            }
+            IpConfig::NotSpecified => ()
+        }
+        if let Some(ip) = &self.ip6 {
+            args.push(format!("--ip6={}", ip).into());
+        }
+        if let Some(mtu) = self.mtu {
+            args.push(format!("--mtu={}", mtu).into());
+        }
+        if let Some(netmask) = &self.netmask {
+            args.push(format!("--netmask={}", netmask).into());
+        }
+        if let Some(name) = &self.veth_name {
+            args.push(format!("--veth-name={}", name).into());
+        }
+        args
+    }
+
+    fn merge(&mut self, other: &InterfaceConfig) {
+        merge_option(&mut self.default_gw, &other.default_gw);
+        merge_option(&mut self.mac, &other.mac);
+        if !matches!(other.ip_config, IpConfig::NotSpecified) {
+            self.ip_config = other.ip_config.clone();
+        }
+        merge_option(&mut self.ip6, &other.ip6);
+        merge_option(&mut self.mtu, &other.mtu);
+        merge_option(&mut self.netmask, &other.netmask);
+        merge_option(&mut self.veth_name, &other.veth_name);
+    }
+}
+
+fn merge_option<T: Clone>(a: &mut Option<T>, b: &Option<T>) {
+    if b.is_some() {
+        *a = b.clone();
+    }
+}
+
+fn merge_with<T: Clone>(a: &mut T, b: &T, specified: bool) {
+    if specified {
+        *a = b.clone();
+    }
+}
+
+impl Profile {
+    /// Layer `other` on top of this profile.
+    ///
+    /// Flags enabled in either profile stay enabled, lists are concatenated, and every option
+    /// `other` specifies replaces the one here.
+    pub fn merge(&mut self, other: &Profile) -> &mut Self {
+        self.verbose |= other.verbose;
+        self.allow_debuggers |= other.allow_debuggers;
+        self.allusers |= other.allusers;
+        self.apparmor |= other.apparmor;
+        self.appimage |= other.appimage;
+        self.caps |= other.caps;
+        merge_with(&mut self.caps_drop, &other.caps_drop, !matches!(other.caps_drop, CapsDrop::NotSpecified));
+        self.bind.extend(other.bind.iter().cloned());
+        self.blacklists.extend(other.blacklists.iter().cloned());
+        merge_option(&mut self.cgroup, &other.cgroup);
+        self.cpu.extend(other.cpu.iter().cloned());
+        self.disable_mnt |= other.disable_mnt;
+        self.deterministic_exit_code |= other.deterministic_exit_code;
+        self.dns.extend(other.dns.iter().cloned());
+        merge_option(&mut self.hostname, &other.hostname);
+        merge_option(&mut self.hosts_file, &other.hosts_file);
+        self.ignore.extend(other.ignore.iter().cloned());
+        self.interface.extend(other.interface.iter().cloned());
+        self.default_net.merge(&other.default_net);
+        merge_with(&mut self.networks, &other.networks, other.networks.is_specified());
+        self.ipc_namespace |= other.ipc_namespace;
+        self.keep_dev_shm |= other.keep_dev_shm;
+        self.keep_var_tmp |= other.keep_var_tmp;
+        self.machine_id |= other.machine_id;
+        self.memory_deny_write_execute |= other.memory_deny_write_execute;
+        merge_option(&mut self.name, &other.name);
+        merge_with(&mut self.netfilter, &other.netfilter, !matches!(other.netfilter, NetFilter::Disable));
+        merge_with(&mut self.netfilter6, &other.netfilter6, !matches!(other.netfilter6, NetFilter::Disable));
+        merge_option(&mut self.join, &other.join);
+        merge_option(&mut self.join_network, &other.join_network);
+        merge_option(&mut self.join_fs, &other.join_fs);
+        merge_option(&mut self.join_or_start, &other.join_or_start);
+        merge_option(&mut self.netns, &other.netns);
+        merge_option(&mut self.nice, &other.nice);
+        self.no3d |= other.no3d;
+        self.noautopulse |= other.noautopulse;
+        self.noblacklist.extend(other.noblacklist.iter().cloned());
+        self.nodbus |= other.nodbus;
+        self.nodvd |= other.nodvd;
+        self.noexec.extend(other.noexec.iter().cloned());
+        self.noexec_writable |= other.noexec_writable;
+        self.nogroups |= other.nogroups;
+        self.nonewprivs |= other.nonewprivs;
+        self.noprofile |= other.noprofile;
+        self.noroot |= other.noroot;
+        self.nosound |= other.nosound;
+        self.notv |= other.notv;
+        self.nou2f |= other.nou2f;
+        self.novideo |= other.novideo;
+        self.nowhitelist.extend(other.nowhitelist.iter().cloned());
+        merge_option(&mut self.output, &other.output);
+        merge_option(&mut self.output_stderr, &other.output_stderr);
+        merge_with(&mut self.overlay, &other.overlay, !matches!(other.overlay, Overlay::NoSpecified));
+        merge_with(&mut self.private, &other.private, !matches!(other.private, Private::NoSpecified));
+        merge_with(&mut self.private_bin, &other.private_bin, !matches!(other.private_bin, PrivateList::NoSpecified));
+        self.private_cache |= other.private_cache;
+        merge_with(&mut self.private_cwd, &other.private_cwd, !matches!(other.private_cwd, Private::NoSpecified));
+        self.private_dev |= other.private_dev;
+        merge_with(&mut self.private_etc, &other.private_etc, !matches!(other.private_etc, PrivateList::NoSpecified));
+        merge_with(&mut self.private_home, &other.private_home, !matches!(other.private_home, PrivateList::NoSpecified));
+        merge_with(&mut self.private_lib, &other.private_lib, !matches!(other.private_lib, PrivateList::NoSpecified));
+        merge_with(&mut self.private_opt, &other.private_opt, !matches!(other.private_opt, PrivateList::NoSpecified));
+        merge_with(&mut self.private_srv, &other.private_srv, !matches!(other.private_srv, PrivateList::NoSpecified));
+        self.private_tmp |= other.private_tmp;
+        merge_option(&mut self.profile, &other.profile);
+        self.protocol.extend(other.protocol.iter().cloned());
+        self.read_only.extend(other.read_only.iter().cloned());
+        self.read_write.extend(other.read_write.iter().cloned());
+        merge_option(&mut self.rlimit, &other.rlimit);
+        merge_option(&mut self.rlimit_cpu, &other.rlimit_cpu);
+        merge_option(&mut self.rlimit_fsize, &other.rlimit_fsize);
+        merge_option(&mut self.rlimit_nofile, &other.rlimit_nofile);
+        merge_option(&mut self.rlimit_nproc, &other.rlimit_nproc);
+        merge_option(&mut self.rlimit_sigpending, &other.rlimit_sigpending);
+        self.remove_env.extend(other.remove_env.iter().cloned());
+        merge_with(&mut self.seccomp, &other.seccomp, !matches!(other.seccomp, Seccomp::NotSpecified));
+        merge_with(&mut self.shell, &other.shell, !matches!(other.shell, Shell::NotSpecified));
+        merge_option(&mut self.timeout, &other.timeout);
+        self.tmpfs.extend(other.tmpfs.iter().cloned());
+        merge_option(&mut self.tunnel, &other.tunnel);
+        self.whitelist.extend(other.whitelist.iter().cloned());
+        self.writable_etc |= other.writable_etc;
+        self.writable_run_user |= other.writable_run_user;
+        self.writable_var |= other.writable_var;
+        self.writable_var_log |= other.writable_var_log;
+        merge_with(&mut self.x11, &other.x11, !matches!(other.x11, X11::NotSpecified));
+        self.sandbox_env.extend(other.sandbox_env.iter().cloned());
+        self.raw_args.extend(other.raw_args.iter().cloned());
+        self
+    }
+}
+
+/// Clones of `base`, each with one of `variations` merged on top.
+fn matrix(base: FireJailCommand, variations: Vec<Profile>) -> impl Iterator<Item=FireJailCommand> {
+    base.consumed.set(true);
+    variations.into_iter().map(move |variation| {
+        let mut command = base.clone();
+        command.merge(&variation);
+        command
+    })
+}
+
+/// Spawn `base` once for every profile in `variations`, merged on top of it.
+pub fn spawn_matrix(base: FireJailCommand, variations: Vec<Profile>) -> impl Iterator<Item=FireJailResult<FireJailChild>> {
+    matrix(base, variations).map(|mut command| command.spawn())
+}
+
+/// Environment variable set for programs relaunched by `FireJailCommand::self_sandbox`.
+pub const SELF_SANDBOX_ENV: &str = "FIREJAIL_SPAWN_SELF_SANDBOX";
+
+/// Whether this process was started by `FireJailCommand::self_sandbox`.
+pub fn is_self_sandboxed() -> bool {
+    std::env::var_os(SELF_SANDBOX_ENV).is_some()
+}
+
+/// Find `program` the way a shell would: paths containing a slash are taken as they are,
+/// bare names are looked up in `PATH`.
+fn find_executable<P: AsRef<OsStr>>(program: P) -> Option<PathBuf> {
+    let program = Path::new(program.as_ref());
+    use std::os::unix::fs::PermissionsExt;
+    let is_executable = |path: &Path| path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+    if program.as_os_str().as_encoded_bytes().contains(&b'/') {
+        let path = program.to_path_buf();
+        return if is_executable(&path) { Some(path) } else { None };
+    }
+    std::env::var_os("PATH")
+        .and_then(|paths| std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|path| is_executable(path)))
+}
+
+fn open_output(path: &Path, mode: FileMode) -> Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(mode == FileMode::Truncate)
+        .append(mode == FileMode::Append)
+        .open(path)
+}
+
+fn templates() -> &'static RwLock<HashMap<String, Profile>> {
+    static TEMPLATES: OnceLock<RwLock<HashMap<String, Profile>>> = OnceLock::new();
+    TEMPLATES.get_or_init(Default::default)
+}
+
+/// Register `profile` under `name` for use with `FireJailCommand::from_template`,
+/// replacing any template previously registered under the same name.
+pub fn register_template<S: AsRef<str>>(name: S, profile: Profile) {
+    templates()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.as_ref().to_string(), profile);
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            verbose: false,
+            allow_debuggers: false,
+            allusers: false,
+            apparmor: false,
+            appimage: false,
+            caps: false,
+            caps_drop: CapsDrop::NotSpecified,
+            bind: Vec::new(),
+            blacklists: Vec::new(),
+            cgroup: None,
+            cpu: vec![],
+            disable_mnt: false,
+            deterministic_exit_code: false,
+            dns: vec![],
+            hostname: None,
+            hosts_file: None,
+            ignore: vec![],
+            interface: vec![],
+            default_net: InterfaceConfig::default(),
+            networks: Net::NotSpecified,
+            ipc_namespace: false,
+            keep_dev_shm: false,
+            keep_var_tmp: false,
+            machine_id: false,
+            memory_deny_write_execute: false,
+            name: None,
+            netfilter: NetFilter::Disable,
+            netfilter6: NetFilter::Disable,
+            join: None,
+            join_network: None,
+            join_fs: None,
+            join_or_start: None,
+            netns: None,
+            nice: None,
+            no3d: false,
+            noautopulse: false,
+            noblacklist: vec![],
+            nodbus: false,
+            nodvd: false,
+            noexec: vec![],
+            noexec_writable: false,
+            nogroups: false,
+            nonewprivs: false,
+            noprofile: false,
+            noroot: false,
+            nosound: false,
+            notv: false,
+            nou2f: false,
+            novideo: false,
+            nowhitelist: vec![],
+            output: None,
+            output_stderr: None,
+            overlay: Overlay::NoSpecified,
+            private: Private::NoSpecified,
+            private_bin: PrivateList::NoSpecified,
+            private_cache: false,
+            private_cwd: Private::NoSpecified,
+            private_dev: false,
+            private_etc: PrivateList::NoSpecified,
+            private_home: PrivateList::NoSpecified,
+            private_lib: PrivateList::NoSpecified,
+            private_opt: PrivateList::NoSpecified,
+            private_srv: PrivateList::NoSpecified,
+            private_tmp: false,
+            profile: None,
+            protocol: vec![],
+            read_only: vec![],
+            read_write: vec![],
+            rlimit: None,
+            rlimit_cpu: None,
+            rlimit_fsize: None,
+            rlimit_nofile: None,
+            rlimit_nproc: None,
+            rlimit_sigpending: None,
+            remove_env: vec![],
+            seccomp: Seccomp::NotSpecified,
+            shell: Shell::NotSpecified,
+            timeout: None,
+            tmpfs: vec![],
+            tunnel: None,
+            whitelist: vec![],
+            writable_etc: false,
+            writable_run_user: false,
+            writable_var: false,
+            writable_var_log: false,
+            x11: X11::NotSpecified,
+            sandbox_env: vec![],
+            raw_args: vec![],
+        }
+    }
+}
+
+impl FireJailCommand {
+    bool_option!(verbose);
+    bool_option!(allow_debuggers);
+    bool_option!(allusers);
+    bool_option!(apparmor);
     bool_option!(appimage);
     bool_option!(caps);
     bool_option!(disable_mnt);
     bool_option!(deterministic_exit_code);
+    bool_option!(private_cache);
+    bool_option!(private_dev);
+    bool_option!(private_tmp);
+    bool_option!(keep_dev_shm);
+    bool_option!(keep_var_tmp);
+    bool_option!(no3d);
+    bool_option!(noautopulse);
+    bool_option!(nodbus);
+    bool_option!(nodvd);
+    bool_option!(nosound);
+    bool_option!(notv);
+    bool_option!(nou2f);
+    bool_option!(novideo);
+    bool_option!(writable_etc);
+    bool_option!(writable_run_user);
+    bool_option!(writable_var);
+    bool_option!(writable_var_log);
+    bool_option!(memory_deny_write_execute);
+    bool_option!(
+        /// Set `PR_SET_NO_NEW_PRIVS`, so no process in the sandbox can gain privileges through
+        /// `execve`: setuid and setgid bits and file capabilities are ignored, and binaries
+        /// such as `sudo`, `su` or `ping` that rely on them stop working.
+        nonewprivs
+    );
+    bool_option!(
+        /// Run the sandbox in a user namespace holding only the current user, so root is not
+        /// available inside it. Some kernels refuse to combine the user namespace with a new
+        /// network namespace, so `validate` warns when `net` configures interfaces.
+        noroot
+    );
+    bool_option!(
+        /// Drop all supplementary groups, leaving only the user's primary group.
+        nogroups
+    );
     inlinablestring_option_replace!(cgroup);
     inlinablestring_option_replace!(hostname);
+    inlinablestring_option_replace!(name);
     path_option_replace!(hosts_file);
+    option_unset!(unset_cgroup, cgroup);
+    option_unset!(unset_hostname, hostname);
+    option_unset!(unset_name, name);
+    option_unset!(unset_hosts_file, hosts_file);
+    option_unset!(unset_nice, nice);
+    option_unset!(unset_netns, netns);
+    option_unset!(unset_tunnel, tunnel);
+
+    /// Name the sandbox `<prefix>-<pid>-<counter>`, unique within this process, and return
+    /// the name for a later `join`.
+    pub fn auto_name(&mut self, prefix: &str) -> String {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("{}-{}-{}", prefix, std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        self.name(&name);
+        name
+    }
+
     pub fn new<S: AsRef<str>>(program: S) -> Self {
+        FireJailCommand::with_executable(OsString::from(program.as_ref()))
+    }
+
+    fn with_executable(executable: OsString) -> Self {
         FireJailCommand {
-            inner: Command::new("firejail"),
-            executable: InlinableString::from(program.as_ref()),
+            executable,
             arg_vec: Vec::new(),
-            profile: Profile {
-                verbose: false,
-                allow_debuggers: false,
-                allusers: false,
-                apparmor: false,
-                appimage: false,
-                caps: false,
-                caps_drop: CapsDrop::NotSpecified,
-                bind: Vec::new(),
-                blacklists: Vec::new(),
-                cgroup: None,
-                cpu: vec![],
-                disable_mnt: false,
-                deterministic_exit_code: false,
-                dns: vec![],
-                hostname: None,
-                hosts_file: None,
-                ignore: vec![],
-                interface: vec![],
-                default_net: InterfaceConfig {
-                    default_gw: None,
-                    mac: None,
-                    ip_config: IpConfig::NotSpecified,
-                    ip6: None,
-                    mtu: None,
-                    netmask: None,
-                    veth_name: None
-                },
-                networks: Net::NotSpecfied,
-                ipc_namespace: false,
-                keep_dev_shm: false,
-                keep_var_tmp: false,
-                machine_id: false,
-                memory_deny_write_execute: false,
-                name: None,
-                netfilter: NetFilter::Disable,
-                netfilter6: NetFilter::Disable,
-                join: None,
-                join_network: None,
-                join_fs: None,
-                join_or_start: None,
-                netns: None,
-                nice: None,
-                no3d: false,
-                noautopulse: false,
-                noblacklist: vec![],
-                nodbus: false,
-                nodvd: false,
-                noexec: vec![],
-                nogroups: false,
-                nonewprivs: false,
-                noprofile: false,
-                noroot: false,
-                nosound: false,
-                notv: false,
-                nou2f: false,
-                novideo: false,
-                nowhitelist: vec![],
-                output: None,
-                output_stderr: None,
-                overlay: Overlay::NoSpecified,
-                private: Private::NoSpecified,
-                private_bin: PrivateList::NoSpecified,
-                private_cache: false,
-                private_cwd: Private::NoSpecified,
-                private_dev: false,
-                private_etc: PrivateList::NoSpecified,
-                private_home: PrivateList::NoSpecified,
-                private_lib: PrivateList::NoSpecified,
-                private_opt: PrivateList::NoSpecified,
-                private_srv: PrivateList::NoSpecified,
-                private_tmp: false,
-                profile: None,
-                protocal: vec![],
-                read_only: vec![],
-                read_write: vec![],
-                rlimit: None,
-                rlimit_cpu: None,
-                rlimit_fsize: None,
-                rlimit_nofile: None,
-                rlimit_nproc: None,
-                rlimit_sigpending: None,
-                remove_env: vec![],
-                seccomp: Seccomp::NotSpecified,
-                shell: Shell::NotSpecified,
-                timeout: None,
-                tmpfs: vec![],
-                tunnel: None,
-                whitelist: vec![],
-                writable_etc: false,
-                writable_run_user: false,
-                writable_var: false,
-                writable_var_log: false,
-                x11: X11::NotSpecified
-            },
+            profile: Profile::default(),
+            sorted: false,
+            warn_on_suspicious_args: false,
+            current_dir: None,
+            env_clear: false,
+            envs: Vec::new(),
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            on_exit: Vec::new(),
+            kill_on_drop: false,
+            consumed: Cell::new(false),
+        }
+    }
+
+    /// Relaunch the current executable, with the current arguments, inside a sandbox.
+    ///
+    /// The child sees `SELF_SANDBOX_ENV` in its environment, and calling `self_sandbox`
+    /// again from there fails instead of re-executing forever; use `is_self_sandboxed` to
+    /// pick the sandboxed code path.
+    pub fn self_sandbox() -> Result<Self> {
+        if is_self_sandboxed() {
+            return Err(std::io::Error::other("already running inside a self sandbox"));
+        }
+        let exe = std::env::current_exe()?;
+        let mut command = FireJailCommand::from(exe);
+        command.arg_vec.extend(std::env::args_os().skip(1));
+        command.env(SELF_SANDBOX_ENV, "1");
+        Ok(command)
+    }
+
+    /// Create a command running `program` under the template registered as `name`.
+    pub fn from_template<N: AsRef<str>, S: AsRef<str>>(name: N, program: S) -> FireJailResult<Self> {
+        let profile = templates()
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name.as_ref())
+            .cloned()
+            .ok_or_else(|| FireJailError::UnknownTemplate(name.as_ref().to_string()))?;
+        Ok(FireJailCommand::with_profile(program, profile))
+    }
+
+    /// Create a command running `program` under `profile`, e.g. one shared by several programs.
+    pub fn with_profile<S: AsRef<str>>(program: S, profile: Profile) -> Self {
+        let mut command = FireJailCommand::new(program);
+        command.profile = profile;
+        command
+    }
+
+    /// Create a command running `program` with the settings of the firejail profile at `path`.
+    ///
+    /// Directives this crate models are applied through the matching builder methods; all
+    /// others are passed on with `raw_arg`, so `seccomp` becomes `--seccomp` and
+    /// `x11 none` becomes `--x11=none`. Relative `include` paths are resolved against the
+    /// directory of `path`, see `from_profile_file_with_base`.
+    pub fn from_profile_file<S: AsRef<str>, P: AsRef<Path>>(program: S, path: P) -> FireJailResult<Self> {
+        let base_dir = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+        FireJailCommand::from_profile_file_with_base(program, path.as_ref(), base_dir)
+    }
+
+    /// Like `from_profile_file`, resolving relative `include` paths against `base_dir`.
+    ///
+    /// The directives of an included file are applied where the `include` appears, and
+    /// includes may nest; as in firejail, relative includes inside an included file are
+    /// resolved against that file's own directory. As with firejail, includes of missing files are skipped, which
+    /// covers the customary optional `.local` files. An include cycle is an `InvalidProfile` error.
+    pub fn from_profile_file_with_base<S, P, B>(program: S, path: P, base_dir: B) -> FireJailResult<Self> where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+        B: AsRef<Path>,
+    {
+        let mut command = FireJailCommand::new(program);
+        command.load_profile_file(path.as_ref(), base_dir.as_ref(), &mut Vec::new())?;
+        Ok(command)
+    }
+
+    fn load_profile_file(&mut self, path: &Path, base_dir: &Path, stack: &mut Vec<PathBuf>) -> FireJailResult<()> {
+        let text = std::fs::read_to_string(path).map_err(FireJailError::Io)?;
+        stack.push(path.canonicalize().map_err(FireJailError::Io)?);
+        for (no, line) in text.lines().enumerate() {
+            let at = |e: String| FireJailError::InvalidProfile(format!("{}:{}: {}", path.display(), no + 1, e));
+            let include = line.trim()
+                .split_once(char::is_whitespace)
+                .filter(|(directive, _)| *directive == "include")
+                .map(|(_, file)| base_dir.join(file.trim()));
+            match include {
+                Some(file) => {
+                    let canonical = match file.canonicalize() {
+                        Ok(canonical) => canonical,
+                        Err(_) if !file.exists() => continue,
+                        Err(e) => return Err(FireJailError::Io(e)),
+                    };
+                    if stack.contains(&canonical) {
+                        return Err(at(format!("include cycle through {}", canonical.display())));
+                    }
+                    self.load_profile_file(&file, file.parent().unwrap_or(base_dir), stack)?;
+                }
+                None => self.apply_profile_line(line).map_err(at)?,
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// Add a `caps.drop` (`drop`, or `None` for `all`) or `caps.keep` line to the capabilities
+    /// collected from earlier lines and included profiles.
+    fn merge_profile_caps(&mut self, drop: Option<Vec<String>>, keep: Vec<String>) -> std::result::Result<(), String> {
+        let keeps = match &self.profile.caps_drop {
+            CapsDrop::Settings { whitelist, .. } => !whitelist.is_empty() || !keep.is_empty(),
+            CapsDrop::DropAll => !keep.is_empty(),
+            CapsDrop::NotSpecified => false,
+        };
+        match (drop, &mut self.profile.caps_drop) {
+            (None, _) if keeps => return Err("caps.drop all conflicts with caps.keep".to_string()),
+            (None, caps) => *caps = CapsDrop::DropAll,
+            (Some(_), CapsDrop::DropAll) if keeps => return Err("caps.keep conflicts with caps.drop all".to_string()),
+            (Some(_), CapsDrop::DropAll) => (),
+            (Some(drop), CapsDrop::Settings { whitelist, blacklist }) => {
+                whitelist.extend(keep.into_iter().map(InlinableString::from));
+                blacklist.extend(drop.into_iter().map(InlinableString::from));
+            }
+            (Some(drop), caps) => *caps = CapsDrop::builder().whitelists(keep).blacklists(drop).build(),
+        }
+        Ok(())
+    }
+
+    fn apply_profile_line(&mut self, line: &str) -> std::result::Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let (directive, value) = match line.split_once(char::is_whitespace) {
+            Some((directive, value)) => (directive, value.trim()),
+            None => (line, ""),
+        };
+        let number = |value: &str| value.parse::<usize>()
+            .map_err(|_| format!("{} expects a number, got {:?}", directive, value));
+        let list = |value: &str| value.split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        match (directive, value) {
+            ("quiet", "") => {}
+            ("allusers", "") => { self.allusers(); }
+            ("apparmor", "") => { self.apparmor(); }
+            ("caps", "") => { self.caps(); }
+            ("deterministic-exit-code", "") => { self.deterministic_exit_code(); }
+            ("disable-mnt", "") => { self.disable_mnt(); }
+            ("private-cache", "") => { self.private_cache(); }
+            ("private-dev", "") => { self.private_dev(); }
+            ("private-tmp", "") => { self.private_tmp(); }
+            ("keep-dev-shm", "") => { self.keep_dev_shm(); }
+            ("keep-var-tmp", "") => { self.keep_var_tmp(); }
+            ("no3d", "") => { self.no3d(); }
+            ("noautopulse", "") => { self.noautopulse(); }
+            ("nodbus", "") => { self.nodbus(); }
+            ("nodvd", "") => { self.nodvd(); }
+            ("nosound", "") => { self.nosound(); }
+            ("notv", "") => { self.notv(); }
+            ("nou2f", "") => { self.nou2f(); }
+            ("novideo", "") => { self.novideo(); }
+            ("writable-etc", "") => { self.writable_etc(); }
+            ("writable-run-user", "") => { self.writable_run_user(); }
+            ("writable-var", "") => { self.writable_var(); }
+            ("writable-var-log", "") => { self.writable_var_log(); }
+            ("private-cwd", "") => { self.private_cwd(Private::Default); }
+            ("private-cwd", dir) => { self.private_cwd(Private::Directory(PathBuf::from(dir))); }
+            ("overlay", "") => { self.overlay(Overlay::Default); }
+            ("overlay-tmp", "") => { self.overlay(Overlay::Tmp); }
+            ("overlay-named", name) if !name.is_empty() => { self.overlay(Overlay::Named(name.into())); }
+            ("private", "") => { self.private(Private::Default); }
+            ("private", dir) => { self.private(Private::Directory(PathBuf::from(dir))); }
+            ("private-bin", binaries) => { self.private_bin(list(binaries)); }
+            ("private-etc", entries) => { self.private_etc(list(entries)); }
+            ("private-home", files) => { self.private_home(list(files)); }
+            ("private-lib", libs) => { self.private_lib(list(libs)); }
+            ("private-opt", entries) => { self.private_opt(list(entries)); }
+            ("private-srv", entries) => { self.private_srv(list(entries)); }
+            ("caps.drop", "all") => { self.merge_profile_caps(None, Vec::new())?; }
+            ("caps.drop", caps) if !caps.is_empty() => { self.merge_profile_caps(Some(list(caps)), Vec::new())?; }
+            ("caps.keep", caps) if !caps.is_empty() => { self.merge_profile_caps(Some(Vec::new()), list(caps))?; }
+            ("net", "none") => { self.net(Net::None); }
+            ("interface", name) if !name.is_empty() => { self.interface(name); }
+            ("netns", name) if !name.is_empty() => { self.netns(name); }
+            ("seccomp", "") => { self.seccomp(Seccomp::Enable); }
+            ("seccomp", calls) => {
+                self.seccomp(Seccomp::List(list(calls).into_iter().map(InlinableString::from).collect()));
+            }
+            ("memory-deny-write-execute", "") => { self.memory_deny_write_execute(); }
+            ("nonewprivs", "") => { self.nonewprivs(); }
+            ("noroot", "") => { self.noroot(); }
+            ("nogroups", "") => { self.nogroups(); }
+            ("seccomp.block-secondary", "") => { self.seccomp(Seccomp::BlockSecondary); }
+            ("seccomp.drop", calls) if !calls.is_empty() => {
+                self.seccomp(Seccomp::Drop(list(calls).into_iter().map(InlinableString::from).collect()));
+            }
+            ("seccomp.keep", calls) if !calls.is_empty() => {
+                self.seccomp(Seccomp::Keep(list(calls).into_iter().map(InlinableString::from).collect()));
+            }
+            ("protocol", families) if !families.is_empty() => {
+                self.protocol_names(list(families));
+            }
+            ("blacklist", path) if !path.is_empty() => { self.blacklist(path); }
+            ("noblacklist", path) if !path.is_empty() => { self.noblacklist(path); }
+            ("whitelist", path) if !path.is_empty() => { self.whitelist(path); }
+            ("nowhitelist", path) if !path.is_empty() => { self.nowhitelist(path); }
+            ("read-only", path) if !path.is_empty() => { self.read_only(path); }
+            ("read-write", path) if !path.is_empty() => { self.read_write(path); }
+            ("noexec", path) if !path.is_empty() => { self.noexec(path); }
+            ("bind", paths) if !paths.is_empty() => {
+                let (a, b) = paths.split_once(',')
+                    .ok_or_else(|| format!("bind expects two comma separated paths, got {:?}", paths))?;
+                self.bind(a.trim(), b.trim());
+            }
+            ("dns", server) if !server.is_empty() => { self.dns(server); }
+            ("hostname", name) if !name.is_empty() => { self.hostname(name); }
+            ("hosts-file", path) if !path.is_empty() => { self.hosts_file(path); }
+            ("env", var) if var.contains('=') => {
+                let (key, val) = var.split_once('=').unwrap();
+                self.sandbox_env(key, val);
+            }
+            ("name", name) if !name.is_empty() => { self.name(name); }
+            ("cgroup", path) if !path.is_empty() => { self.cgroup(path); }
+            ("ignore", option) if !option.is_empty() => { self.ignore(option); }
+            ("cpu", cpus) if !cpus.is_empty() => {
+                let cpus = list(cpus).iter().map(|x| number(x)).collect::<std::result::Result<Vec<_>, _>>()?;
+                self.cpus(cpus);
+            }
+            ("nice", n) => { self.nice(number(n)?); }
+            ("rlimit-as", n) => { self.rlimit_as(number(n)?); }
+            ("rlimit-cpu", n) => { self.rlimit_cpu(number(n)?); }
+            ("rlimit-fsize", n) => { self.rlimit_fsize(parse_size(n).map_err(|e| e.to_string())?); }
+            ("rlimit-nofile", n) => { self.rlimit_nofile(number(n)?); }
+            ("rlimit-nproc", n) => { self.rlimit_nproc(number(n)?); }
+            ("rlimit-sigpending", n) => { self.rlimit_sigpending(number(n)?); }
+            (_, "") => { self.raw_arg(format!("--{}", directive)); }
+            (_, value) => { self.raw_arg(format!("--{}={}", directive, value)); }
+        }
+        Ok(())
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Take the configured profile, to run other programs under it with `with_profile`.
+    /// The program and its arguments are discarded.
+    pub fn into_profile(mut self) -> Profile {
+        self.consumed.set(true);
+        std::mem::take(&mut self.profile)
+    }
+
+    /// Layer `profile` on top of this command's profile; see `Profile::merge`.
+    pub fn merge(&mut self, profile: &Profile) -> &mut Self {
+        self.profile.merge(profile);
+        self
+    }
+
+    /// Start firejail in `dir`. The sandboxed program starts there too, unless `private_cwd`
+    /// picks another directory.
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.current_dir.replace(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Kill the sandbox after `t`, both through firejail's `--timeout` and from this process
+    /// while waiting for it; `SandboxOutput::timed_out` tells whether that happened.
+    pub fn timeout(&mut self, t: Timeout) -> &mut Self {
+        self.profile.timeout.replace(t);
+        self
+    }
+
+    pub fn nice(&mut self, n: usize) -> &mut Self {
+        self.profile.nice.replace(n);
+        self
+    }
+
+    /// Limit the address space of each process in the sandbox to `bytes`.
+    pub fn rlimit_as(&mut self, bytes: usize) -> &mut Self {
+        self.profile.rlimit.replace(bytes);
+        self
+    }
+
+    /// Limit the CPU time of each process in the sandbox to `secs` seconds.
+    pub fn rlimit_cpu(&mut self, secs: usize) -> &mut Self {
+        self.profile.rlimit_cpu.replace(secs);
+        self
+    }
+
+    /// Limit the size of files the sandbox can create to `bytes`.
+    pub fn rlimit_fsize(&mut self, bytes: usize) -> &mut Self {
+        self.profile.rlimit_fsize.replace(bytes);
+        self
+    }
+
+    /// Like `rlimit_fsize`, with a size such as `"10M"` read by `parse_size`.
+    pub fn rlimit_fsize_str<S: AsRef<str>>(&mut self, size: S) -> FireJailResult<&mut Self> {
+        let bytes = parse_size(size.as_ref())?;
+        Ok(self.rlimit_fsize(bytes))
+    }
+
+    /// Limit the number of file descriptors each process in the sandbox can open to `n`.
+    pub fn rlimit_nofile(&mut self, n: usize) -> &mut Self {
+        self.profile.rlimit_nofile.replace(n);
+        self
+    }
+
+    /// Limit the number of processes to `n`. On Linux the limit is per user: every process of
+    /// the user counts against it, including those running outside the sandbox.
+    pub fn rlimit_nproc(&mut self, n: usize) -> &mut Self {
+        self.profile.rlimit_nproc.replace(n);
+        self
+    }
+
+    /// Limit the number of signals that can be queued for the sandbox's user to `n`.
+    pub fn rlimit_sigpending(&mut self, n: usize) -> &mut Self {
+        self.profile.rlimit_sigpending.replace(n);
+        self
+    }
+
+    /// The resource limits currently set, in the form `rlimits` takes.
+    fn current_rlimits(&self) -> Rlimits {
+        Rlimits {
+            address_space: self.profile.rlimit,
+            cpu: self.profile.rlimit_cpu,
+            fsize: self.profile.rlimit_fsize,
+            nofile: self.profile.rlimit_nofile,
+            nproc: self.profile.rlimit_nproc,
+            sigpending: self.profile.rlimit_sigpending,
+        }
+    }
+
+    /// Replace all resource limits with `r`; `None` fields clear the matching limit.
+    pub fn rlimits(&mut self, r: Rlimits) -> &mut Self {
+        self.profile.rlimit = r.address_space;
+        self.profile.rlimit_cpu = r.cpu;
+        self.profile.rlimit_fsize = r.fsize;
+        self.profile.rlimit_nofile = r.nofile;
+        self.profile.rlimit_nproc = r.nproc;
+        self.profile.rlimit_sigpending = r.sigpending;
+        self
+    }
+
+    /// Run as a low-priority batch job: `--nice=10`, pinned to the last CPU this process
+    /// is allowed to run on. Without a readable affinity mask only the niceness is set.
+    pub fn background(&mut self) -> &mut Self {
+        let last = allowed_cpus().and_then(|cpus| cpus.last().copied());
+        self.background_with(10, last)
+    }
+
+    /// Like `background`, with an explicit niceness and set of CPUs.
+    pub fn background_with<N: AsPrimitive<usize>, I : IntoIterator<Item = N>>(&mut self, nice: usize, cpus: I) -> &mut Self {
+        self.profile.cpu.clear();
+        self.nice(nice).cpus(cpus)
+    }
+
+    pub fn cpu(&mut self, no: usize) -> &mut Self {
+        self.profile.cpu.push(no);
+        self
+    }
+
+    pub fn cpus<N: AsPrimitive<usize>, I : IntoIterator<Item = N>>(&mut self, iter: I) -> &mut Self {
+        self.profile.cpu.extend(iter.into_iter().map(|x|x.as_()));
+        self
+    }
+
+    pub fn blacklist<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.blacklists.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn blacklists<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.blacklists.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Restrict the socket families available in the sandbox to `flags`.
+    ///
+    /// Repeated calls accumulate; the families are emitted as one `--protocol` flag.
+    pub fn protocol(&mut self, flags: Protocol) -> &mut Self {
+        self.profile.protocol.extend(flags.names().map(InlinableString::from));
+        self
+    }
+
+    /// Like `protocol`, taking the families one by one, e.g. from a configuration list.
+    pub fn protocol_set<I: IntoIterator<Item=Protocol>>(&mut self, protocols: I) -> &mut Self {
+        let flags = protocols.into_iter().fold(Protocol::empty(), |a, b| a | b);
+        self.protocol(flags)
+    }
+
+    /// Like `protocol`, for families a newer firejail may add before `Protocol` covers them.
+    /// `validate` rejects names this crate does not know.
+    pub fn protocol_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.profile.protocol.push(InlinableString::from(name.as_ref()));
+        self
+    }
+
+    pub fn protocol_names<I, S: AsRef<str>>(&mut self, names: I) -> &mut Self where
+        I: IntoIterator<Item=S>
+    {
+        self.profile.protocol.extend(names.into_iter().map(|x| InlinableString::from(x.as_ref())));
+        self
+    }
+
+    /// Exempt `path` from blacklisting, including blacklists from the default profile.
+    /// firejail only honours `--noblacklist` ahead of the `--blacklist` it cancels, so these are
+    /// always emitted before every blacklist.
+    pub fn noblacklist<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.noblacklist.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn noblacklists<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.noblacklist.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Whitelist `path`, emitted as one `--whitelist=` per entry. The path must be absolute
+    /// (or start with `~/` or a firejail macro such as `${HOME}`); `validate` rejects relative
+    /// paths.
+    pub fn whitelist<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.whitelist.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn whitelists<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.whitelist.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Stop whitelisting `path`, e.g. one whitelisted by an included profile. Emitted after all
+    /// `--whitelist` flags.
+    pub fn nowhitelist<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.nowhitelist.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn nowhitelists<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.nowhitelist.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Whitelist `path` even if a loaded profile blacklists it.
+    ///
+    /// firejail refuses to whitelist a blacklisted path unless it is also passed to
+    /// `--noblacklist`, so this adds the path to both lists.
+    pub fn whitelist_unblacklisted<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.noblacklist(path.as_ref()).whitelist(path)
+    }
+
+    /// Mount an empty tmpfs over `path`, emitted as one `--tmpfs=` per entry in insertion order.
+    /// Unless firejail runs as root, it only accepts directories owned by the user.
+    pub fn tmpfs<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.tmpfs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn tmpfses<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.tmpfs.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Remount `path` with `noexec`, `nodev` and `nosuid`, emitted as one `--noexec=` per entry
+    /// in insertion order.
+    pub fn noexec<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.noexec.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn noexecs<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.noexec.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Also apply `--noexec` to every tmpfs, every `read_write` path and every bind target that
+    /// is not read-only, so nothing the sandbox can write to can be executed.
+    pub fn noexec_writable(&mut self, enable: bool) -> &mut Self {
+        self.profile.noexec_writable = enable;
+        self
+    }
+
+    /// Make `path` read-only, emitted as one `--read-only=` per entry.
+    pub fn read_only<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.read_only.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn read_onlys<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.read_only.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Make `path` writable again, typically below a directory a profile made read-only.
+    /// firejail applies these flags in order, and every `--read-write=` is emitted after all
+    /// `--read-only=` flags, so on overlapping paths the writable one wins.
+    pub fn read_write<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.profile.read_write.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn read_writes<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.read_write.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Mount the program's binary, and the directory holding it, read-only, so that a
+    /// compromised sandbox cannot replace its own executable.
+    ///
+    /// The program is looked up in `PATH` and symlinks are resolved; an error is returned
+    /// if it cannot be found.
+    pub fn lock_down_binary(&mut self) -> Result<&mut Self> {
+        let binary = find_executable(&self.executable)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound,
+                                               format!("{} not found in PATH", self.executable.to_string_lossy())))?
+            .canonicalize()?;
+        if let Some(dir) = binary.parent() {
+            self.read_only(dir);
+        }
+        Ok(self.read_only(binary))
+    }
+
+    /// Mount `a` over `b` inside the sandbox.
+    ///
+    /// firejail separates the two paths with a comma and has no way to escape one, so paths
+    /// containing a comma are rejected by `validate` and `spawn`.
+    pub fn bind<A: AsRef<Path>, B: AsRef<Path>>(&mut self, a: A, b: B) -> &mut Self {
+        self.profile.bind.push((a.as_ref().to_path_buf(), b.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Make a single log file writable, instead of the whole of `/var/log`.
+    ///
+    /// firejail mounts a clone of `/var/log` unless `--writable-var-log` is given; this binds
+    /// the host's file over its clone, so writes reach the host file while the rest of
+    /// `/var/log` stays a throwaway copy. Like every `--bind`, this needs firejail to run as root.
+    pub fn writable_log_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.bind(path.as_ref(), path.as_ref())
+    }
+
+    pub fn binds<I, A: AsRef<Path>, B: AsRef<Path>>(&mut self, binds: I) -> &mut Self where
+        I: IntoIterator<Item=(A, B)>
+    {
+        self.profile.bind.extend(binds.into_iter().map(|(a, b)| (
+            a.as_ref().to_path_buf(), b.as_ref().to_path_buf()
+        )));
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self where
+        I: IntoIterator<Item=S>,
+        S: AsRef<str> {
+        self.arg_vec.extend(args.into_iter().map(|x| OsString::from(x.as_ref())));
+        self
+    }
+
+    /// Use the DNS server at address `arg`. firejail honors at most three servers;
+    /// `validate` rejects more, as well as anything that is not an IP address.
+    pub fn dns<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
+        self.profile.dns.push(InlinableString::from(arg.as_ref()));
+        self
+    }
+
+    pub fn dnss<I, S>(&mut self, args: I) -> &mut Self where
+        I: IntoIterator<Item=S>,
+        S: AsRef<str> {
+        self.profile.dns.extend(args.into_iter().map(|x| InlinableString::from(x.as_ref())));
+        self
+    }
+
+    pub fn ignore<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
+        self.profile.ignore.push(InlinableString::from(arg.as_ref()));
+        self
+    }
+
+    pub fn ignores<I, S>(&mut self, args: I) -> &mut Self where
+        I: IntoIterator<Item=S>,
+        S: AsRef<str> {
+        self.profile.ignore.extend(args.into_iter().map(|x| InlinableString::from(x.as_ref())));
+        self
+    }
+
+    /// Set `key` to `val` inside the sandbox with `--env`.
+    ///
+    /// Unlike `env`, which affects the firejail process, this survives firejail's own
+    /// environment cleanup.
+    pub fn sandbox_env<K: AsRef<str>, V: AsRef<str>>(&mut self, key: K, val: V) -> &mut Self {
+        self.profile.sandbox_env.push((InlinableString::from(key.as_ref()), InlinableString::from(val.as_ref())));
+        self
+    }
+
+    /// Set `LANG` inside the sandbox, e.g. `en_US.UTF-8`. `validate` rejects an empty value.
+    pub fn set_locale(&mut self, lang: &str) -> &mut Self {
+        self.sandbox_env("LANG", lang)
+    }
+
+    /// Set `TZ` inside the sandbox, e.g. `Europe/Berlin`. `validate` rejects an empty value.
+    pub fn set_timezone(&mut self, tz: &str) -> &mut Self {
+        self.sandbox_env("TZ", tz)
+    }
+
+    /// Pass `arg` to firejail verbatim, for options this crate does not model.
+    pub fn raw_arg<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
+        self.profile.raw_args.push(InlinableString::from(arg.as_ref()));
+        self
+    }
+
+    pub fn arg<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
+        self.arg_vec.push(OsString::from(arg.as_ref()));
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env_clear = true;
+        self.envs.clear();
+        self
+    }
+
+    pub fn env_remove<S: AsRef<OsStr>>(&mut self, key: S) -> &mut Self {
+        self.envs.push((key.as_ref().to_os_string(), None));
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+        where
+            I: IntoIterator<Item=(K, V)>,
+            K: AsRef<OsStr>,
+            V: AsRef<OsStr>,
+    {
+        self.envs.extend(vars.into_iter()
+            .map(|(k, v)| (k.as_ref().to_os_string(), Some(v.as_ref().to_os_string()))));
+        self
+    }
+
+    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut Self
+        where
+            K: AsRef<OsStr>,
+            V: AsRef<OsStr>,
+    {
+        self.envs.push((key.as_ref().to_os_string(), Some(val.as_ref().to_os_string())));
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.stderr.replace(cfg.into());
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.stdout.replace(cfg.into());
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.stdin.replace(cfg.into());
+        self
+    }
+
+    /// Give the sandbox an empty stdin, so that programs reading it see end-of-file instead
+    /// of blocking on a terminal.
+    ///
+    /// Like `std::process::Command`, stdin is inherited unless configured otherwise: closing
+    /// it by default would break interactive programs.
+    pub fn close_stdin(&mut self) -> &mut Self {
+        self.stdin(Stdio::null())
+    }
+
+    /// Send the sandbox's stdout to the file at `path`, creating it if needed.
+    pub fn stdout_file<P: AsRef<Path>>(&mut self, path: P, mode: FileMode) -> Result<&mut Self> {
+        let file = open_output(path.as_ref(), mode)?;
+        Ok(self.stdout(file))
+    }
+
+    /// Send the sandbox's stderr to the file at `path`, creating it if needed.
+    pub fn stderr_file<P: AsRef<Path>>(&mut self, path: P, mode: FileMode) -> Result<&mut Self> {
+        let file = open_output(path.as_ref(), mode)?;
+        Ok(self.stderr(file))
+    }
+
+    /// A restrictive baseline for running untrusted code: all capabilities dropped, the
+    /// default seccomp filter, `--nonewprivs`, `--noroot`, `--nogroups` and, unless changed
+    /// with `hardened_with`, `HARDENED_NPROC` processes at most.
+    pub fn hardened(&mut self) -> &mut Self {
+        self.hardened_with(ResourceConfig::default())
+    }
+
+    /// Like `hardened`, applying `cfg` on top; `cfg.processes` replaces the default process limit.
+    pub fn hardened_with(&mut self, cfg: ResourceConfig) -> &mut Self {
+        let cfg = ResourceConfig {
+            processes: cfg.processes.or(Some(HARDENED_NPROC)),
+            ..cfg
+        };
+        self.caps_drop(CapsDrop::drop_all())
+            .seccomp(Seccomp::Enable)
+            .nonewprivs()
+            .noroot()
+            .nogroups()
+            .resource_limited(cfg)
+    }
+
+    /// A mostly immutable filesystem view: `IMMUTABLE_ROOT_READ_ONLY` is mounted read-only
+    /// and each of `IMMUTABLE_ROOT_SCRATCH` gets a fresh tmpfs.
+    pub fn immutable_root(&mut self) -> &mut Self {
+        self.immutable_root_with(IMMUTABLE_ROOT_READ_ONLY, IMMUTABLE_ROOT_SCRATCH)
+    }
+
+    /// Like `immutable_root`, with custom path sets.
+    ///
+    /// The paths keep their order within each set, and all `--tmpfs` flags are emitted
+    /// before the `--read-only` ones.
+    pub fn immutable_root_with<I, J, P, Q>(&mut self, read_only: I, scratch: J) -> &mut Self where
+        I: IntoIterator<Item=P>,
+        J: IntoIterator<Item=Q>,
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.read_onlys(read_only).tmpfses(scratch)
+    }
+
+    /// Apply all limits set in `cfg`, translating them to `--cpu`, `--cgroup` and `--rlimit-*`.
+    ///
+    /// Unlike `rlimits`, this only adds limits: unset fields of `cfg` keep the current value.
+    pub fn resource_limited(&mut self, cfg: ResourceConfig) -> &mut Self {
+        if !cfg.cpus.is_empty() {
+            self.profile.cpu = cfg.cpus;
+        }
+        if cfg.cgroup.is_some() {
+            self.profile.cgroup = cfg.cgroup;
+        }
+        let current = self.current_rlimits();
+        self.rlimits(Rlimits {
+            address_space: cfg.memory.or(current.address_space),
+            cpu: cfg.cpu_time.or(current.cpu),
+            nofile: cfg.open_files.or(current.nofile),
+            nproc: cfg.processes.or(current.nproc),
+            ..current
+        })
+    }
+
+    /// Emits `--caps.drop`/`--caps.keep`, independently of `caps`, which enables firejail's
+    /// default capability filter.
+    pub fn caps_drop(&mut self, cfg: CapsDrop) -> &mut Self {
+        self.profile.caps_drop = cfg;
+        self
+    }
+
+    /// Run the program inside an already running sandbox instead of creating a new one.
+    ///
+    /// The joined sandbox keeps its own configuration, so options which would set up a new
+    /// sandbox cannot take effect: `validate` rejects a network set with `net`, and warns
+    /// about a `hostname`.
+    pub fn join(&mut self, j: Join) -> &mut Self {
+        self.profile.join.replace(j);
+        self
+    }
+
+    /// Join only the network namespace of a running sandbox, keeping the host filesystem.
+    /// This is a subset of `join`, and `validate` rejects using both.
+    pub fn join_network(&mut self, j: Join) -> &mut Self {
+        self.profile.join_network.replace(j);
+        self
+    }
+
+    /// Join only the mount namespace of a running sandbox, seeing its filesystem. This is a
+    /// subset of `join`, and `validate` rejects using both.
+    pub fn join_fs(&mut self, j: Join) -> &mut Self {
+        self.profile.join_fs.replace(j);
+        self
+    }
+
+    /// Join the sandbox called `name` if it is running, or else start a new one under that
+    /// name. This names the sandbox like `name` does; `validate` rejects a different `name`.
+    pub fn join_or_start<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.profile.join_or_start = Some(InlinableString::from(name.as_ref()));
+        self
+    }
+
+    /// Keep `dev` available in the sandbox, typically together with `private_dev`.
+    ///
+    /// `Device::Shm` emits `--keep-dev-shm`, which `check` flags on firejail releases that
+    /// lack it. The other devices are kept by `--private-dev` anyway, so for them this only
+    /// undoes the matching `--no3d`, `--nosound`, `--novideo`, `--nodvd`, `--notv` or
+    /// `--nou2f`, and logs a `keep-dev-redundant` warning if there is nothing to undo.
+    pub fn keep_dev(&mut self, dev: Device) -> &mut Self {
+        let (blocked, flag) = match dev {
+            Device::Shm => {
+                self.profile.keep_dev_shm = true;
+                return self;
+            }
+            Device::Dri => (&mut self.profile.no3d, "--no3d"),
+            Device::Sound => (&mut self.profile.nosound, "--nosound"),
+            Device::Video => (&mut self.profile.novideo, "--novideo"),
+            Device::Dvd => (&mut self.profile.nodvd, "--nodvd"),
+            Device::Tv => (&mut self.profile.notv, "--notv"),
+            Device::U2f => (&mut self.profile.nou2f, "--nou2f"),
+        };
+        if !std::mem::take(blocked) {
+            log_warning(&Warning::new(
+                "keep-dev-redundant",
+                format!("{:?} devices are kept unless {} is set, which it is not", dev, flag),
+            ));
+        }
+        self
+    }
+
+    /// Install a seccomp filter: `Enable` is firejail's default blocklist, `List` blocks extra
+    /// syscalls on top of it, `Drop` blocks exactly the listed ones and `Keep` allows only the
+    /// listed ones. firejail treats `--seccomp=` and `--seccomp.drop`/`--seccomp.keep` as
+    /// mutually exclusive, so only one variant can be set; an empty `List` is plain
+    /// `--seccomp`, while an empty `Drop` or `Keep` emits nothing.
+    pub fn seccomp(&mut self, s: Seccomp) -> &mut Self {
+        self.profile.seccomp = s;
+        self
+    }
+
+    /// Enable the default seccomp filter, but allow `syscalls` it would block.
+    ///
+    /// This is firejail's `--seccomp=!syscall,...` form, which removes entries from the
+    /// default blocklist. `Seccomp::Keep` is different: `--seccomp.keep` replaces the filter
+    /// with an allowlist, blocking every syscall not listed, so `--seccomp --seccomp.keep=...`
+    /// would not keep the default filter at all.
+    ///
+    /// A `Drop`, `Keep` or `BlockSecondary` filter set earlier is replaced, which is logged
+    /// as a `seccomp-replaced` warning.
+    pub fn seccomp_with_extra_keep<I, S>(&mut self, syscalls: I) -> &mut Self where
+        I: IntoIterator<Item=S>,
+        S: AsRef<str> {
+        let keep = syscalls.into_iter().map(|x| InlinableString::from(format!("!{}", x.as_ref())));
+        match &mut self.profile.seccomp {
+            Seccomp::List(calls) => calls.extend(keep),
+            other => {
+                if !matches!(other, Seccomp::NotSpecified | Seccomp::Enable) {
+                    log_warning(&Warning::new(
+                        "seccomp-replaced",
+                        format!("seccomp_with_extra_keep replaces the {:?} seccomp filter of {}", other, self.executable.to_string_lossy()),
+                    ));
+                }
+                *other = Seccomp::List(keep.collect());
+            }
+        }
+        self
+    }
+
+    /// Stack an overlay filesystem on top of the root filesystem, discarding (`Overlay::Tmp`)
+    /// or persisting (`Overlay::Default`, `Overlay::Named`) changes made inside the sandbox.
+    /// firejail refuses to combine an overlay with `private`, so `validate` rejects it.
+    pub fn overlay(&mut self, o: Overlay) -> &mut Self {
+        self.profile.overlay = o;
+        self
+    }
+
+    pub fn net(&mut self, net: Net) -> &mut Self {
+        #[allow(deprecated)]
+        let net = match net {
+            Net::NotSpecfied => Net::NotSpecified,
+            net => net,
+        };
+        self.profile.networks = net;
+        self
+    }
+
+    /// Filter the sandbox's network traffic; only takes effect with a network set by `net`.
+    pub fn netfilter(&mut self, nf: NetFilter) -> &mut Self {
+        self.profile.netfilter = nf;
+        self
+    }
+
+    /// Like `netfilter`, with ip6tables rules for IPv6 traffic. This also needs a network
+    /// set by `net`; `validate` warns otherwise.
+    pub fn netfilter6(&mut self, nf: NetFilter) -> &mut Self {
+        self.profile.netfilter6 = nf;
+        self
+    }
+
+    /// Run inside the existing network namespace `name`, e.g. one created with `ip netns add`.
+    /// This replaces `net`; `validate` rejects setting both.
+    pub fn netns<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.profile.netns = Some(InlinableString::from(name.as_ref()));
+        self
+    }
+
+    /// Route the sandbox's traffic through the firetunnel tunnel `t`, or the default one if
+    /// empty. firetunnel has to be installed and the tunnel set up beforehand.
+    pub fn tunnel<S: AsRef<str>>(&mut self, t: S) -> &mut Self {
+        self.profile.tunnel = Some(InlinableString::from(t.as_ref()));
+        self
+    }
+
+    /// Move the host interface `name` into the sandbox, unlike `net`, which creates a new
+    /// one. Needs firejail to run as root.
+    pub fn interface<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.profile.interface.push(InlinableString::from(name.as_ref()));
+        self
+    }
+
+    pub fn interfaces<I, S: AsRef<str>>(&mut self, names: I) -> &mut Self where
+        I: IntoIterator<Item=S>
+    {
+        self.profile.interface.extend(names.into_iter().map(|x| InlinableString::from(x.as_ref())));
+        self
+    }
+
+    /// Run without any network access (`--net=none`).
+    ///
+    /// DNS servers and interfaces configured earlier are discarded, since they would only
+    /// make firejail fail once the sandbox has no network.
+    pub fn no_network(&mut self) -> &mut Self {
+        self.profile.dns.clear();
+        self.profile.interface.clear();
+        self.net(Net::None)
+    }
+
+    /// Like `no_network`, but also restrict sockets to `--protocol=unix`, replacing any
+    /// protocol families set before, so that only local IPC remains possible.
+    pub fn default_deny_network(&mut self) -> &mut Self {
+        self.profile.protocol.clear();
+        self.no_network().protocol(Protocol::UNIX)
+    }
+
+    /// Mount a fresh, empty home directory (`--private`), or use the host directory in
+    /// `Private::Directory` as home (`--private=<dir>`). Since `~/.cache` lives in home, this
+    /// makes `private_cache` redundant; `validate` reports the combination.
+    pub fn private(&mut self, p: Private) -> &mut Self {
+        self.profile.private = p;
+        self
+    }
+
+    /// Restrict the binaries in `/bin`, `/usr/bin` and friends to `binaries`.
+    ///
+    /// firejail looks the programs up by name, so paths are reduced to their file name.
+    /// Misspelled names are silently dropped by firejail; `check_private_bin` catches them
+    /// ahead of time. `--private-bin` takes at least one program, so an empty list
+    /// emits nothing.
+    pub fn private_bin<I, P: AsRef<Path>>(&mut self, binaries: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        let names: Vec<_> = binaries.into_iter()
+            .map(|x| x.as_ref().file_name().map_or_else(|| x.as_ref().to_path_buf(), PathBuf::from))
+            .collect();
+        self.profile.private_bin.extend(names);
+        self
+    }
+
+    /// Return the `private_bin` entries that do not resolve to an executable in the host PATH.
+    pub fn check_private_bin(&self) -> Vec<String> {
+        match &self.profile.private_bin {
+            PrivateList::Files(binaries) => binaries.iter()
+                .map(|x| x.to_string_lossy().into_owned())
+                .filter(|x| find_executable(x).is_none())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Choose the working directory inside the sandbox: `Private::Default` starts the program
+    /// in the home directory, `Private::Directory` in the given one. Either overrides
+    /// `current_dir`, which `validate` reports.
+    pub fn private_cwd(&mut self, p: Private) -> &mut Self {
+        self.profile.private_cwd = p;
+        self
+    }
+
+    /// Replace `/etc` with a copy holding only `entries`, such as `hosts` or `ssl`. An empty
+    /// list emits a bare `--private-etc`.
+    pub fn private_etc<I, P: AsRef<Path>>(&mut self, entries: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.private_etc.extend(entries);
+        self
+    }
+
+    /// Start with an empty home directory holding copies of `files`, given relative to the
+    /// home directory or as absolute paths within it.
+    pub fn private_home<I, P: AsRef<Path>>(&mut self, files: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.private_home.extend(files);
+        self
+    }
+
+    /// Replace `/opt` with a copy holding only the subdirectories `entries`. An empty list
+    /// emits a bare `--private-opt`, for an empty `/opt`.
+    pub fn private_opt<I, P: AsRef<Path>>(&mut self, entries: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.private_opt.extend(entries);
+        self
+    }
+
+    /// Replace `/srv` with a copy holding only `entries`. An empty list emits a bare
+    /// `--private-srv`, for an empty `/srv`.
+    pub fn private_srv<I, P: AsRef<Path>>(&mut self, entries: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.private_srv.extend(entries);
+        self
+    }
+
+    /// Restrict the visible libraries to `libs`, plus those firejail finds the program needs.
+    /// An empty list emits a bare `--private-lib`, keeping only the latter.
+    ///
+    /// `--private-lib` is experimental in firejail; `check` reports its use.
+    pub fn private_lib<I, P: AsRef<Path>>(&mut self, libs: I) -> &mut Self where
+        I: IntoIterator<Item=P>
+    {
+        self.profile.private_lib.extend(libs);
+        self
+    }
+
+    /// Populate `--private-lib` with the shared libraries `ldd` reports for the program.
+    ///
+    /// If the program cannot be found or `ldd` fails, nothing is added and a warning is
+    /// logged through the warning hook.
+    pub fn private_lib_auto(&mut self) -> &mut Self {
+        let output = find_executable(&self.executable)
+            .ok_or_else(|| format!("{} not found in PATH", self.executable.to_string_lossy()))
+            .and_then(|binary| Command::new("ldd").arg(binary).output().map_err(|e| e.to_string()))
+            .and_then(|output| if output.status.success() {
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            } else {
+                Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            });
+        match output {
+            Ok(output) => self.private_lib(ldd_libraries(&output)),
+            Err(e) => {
+                log_warning(&Warning::new(
+                    "private-lib-auto",
+                    format!("cannot list the libraries of {}: {}", self.executable.to_string_lossy(), e),
+                ));
+                self
+            }
+        }
+    }
+
+    /// Add the `/etc` entries `binary` is known to need to `--private-etc`.
+    ///
+    /// This is best-effort: the entries come from a small curated table of common tools
+    /// (plus the basics every dynamically linked program reads), not from tracing the binary.
+    /// Programs missing from the table only get the basics. Entries already present are not
+    /// duplicated.
+    pub fn private_etc_for(&mut self, binary: &str) -> &mut Self {
+        let name = Path::new(binary)
+            .file_name()
+            .and_then(|x| x.to_str())
+            .unwrap_or(binary);
+        let extra = PRIVATE_ETC_TABLE.iter()
+            .find(|(tool, _)| *tool == name)
+            .map(|(_, entries)| *entries)
+            .unwrap_or(&[]);
+        let existing = match &self.profile.private_etc {
+            PrivateList::Files(files) => files.clone(),
+            _ => Vec::new(),
+        };
+        let mut missing = Vec::new();
+        for entry in PRIVATE_ETC_BASE.iter().chain(extra) {
+            let entry = Path::new(entry);
+            if !existing.iter().any(|x| x == entry) && !missing.contains(&entry) {
+                missing.push(entry);
+            }
+        }
+        self.private_etc(missing)
+    }
+
+    /// Check the configuration for conflicting options, which firejail would reject or
+    /// silently misapply, and for argument lists too long to pass to firejail. Then collect
+    /// warnings about combinations which are legal but probably not what is intended.
+    pub fn validate(&self) -> FireJailResult<Vec<Warning>> {
+        self.conflicts()?;
+        self.check_arg_length()?;
+        Ok(self.warnings())
+    }
+
+    fn check_arg_length(&self) -> FireJailResult<()> {
+        let args = self.build_args();
+        // Every argument is passed to execve NUL-terminated and with a pointer to it.
+        let size = args.iter()
+            .map(|x| x.len() + 1 + std::mem::size_of::<usize>())
+            .sum::<usize>();
+        if size > ARG_BYTES_LIMIT || args.iter().any(|x| x.len() >= ARG_STRLEN_LIMIT) {
+            return Err(FireJailError::ArgumentListTooLong(size));
+        }
+        Ok(())
+    }
+
+    fn check_binds(&self) -> FireJailResult<()> {
+        let comma = self.profile.bind.iter()
+            .flat_map(|(a, b)| [a, b])
+            .find(|x| x.as_os_str().as_encoded_bytes().contains(&b','));
+        match comma {
+            Some(path) => Err(FireJailError::InvalidProfile(format!(
+                "bind path {} contains a comma, which firejail cannot parse", path.display()))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_whitelist(&self) -> FireJailResult<()> {
+        // firejail expands `~` and `${HOME}`-style macros itself; anything else must be absolute.
+        let relative = self.profile.whitelist.iter().find(|x| {
+            let s = x.to_string_lossy();
+            !x.is_absolute() && !s.starts_with("~/") && !s.starts_with("${")
+        });
+        match relative {
+            Some(path) => Err(FireJailError::InvalidProfile(format!(
+                "whitelist path {} is relative, firejail only accepts absolute paths", path.display()))),
+            None => Ok(()),
+        }
+    }
+
+    fn conflicts(&self) -> FireJailResult<()> {
+        if let (Some(join), Some(name)) = (&self.profile.join_or_start, &self.profile.name) {
+            if join != name {
+                return Err(FireJailError::ConflictingOptions(format!(
+                    "--join-or-start={} would start a sandbox named {}, not {}", join, join, name)));
+            }
+        }
+        if let Some(join) = &self.profile.join {
+            if self.profile.join_network.is_some() {
+                return Err(FireJailError::ConflictingOptions(format!(
+                    "--join={} already joins the network, --join-network cannot be used with it", join)));
+            }
+            if self.profile.join_fs.is_some() {
+                return Err(FireJailError::ConflictingOptions(format!(
+                    "--join={} already joins the filesystem, --join-filesystem cannot be used with it", join)));
+            }
+            if self.profile.networks.is_specified() {
+                return Err(FireJailError::ConflictingOptions(format!(
+                    "--join={} reuses the network of a running sandbox, so --net cannot set one up", join)));
+            }
+        }
+        if let Some(server) = self.profile.dns.iter().find(|x| x.parse::<std::net::IpAddr>().is_err()) {
+            return Err(FireJailError::InvalidProfile(format!("DNS server {:?} is not an IP address", server.as_ref())));
+        }
+        if self.profile.dns.len() > MAX_DNS_SERVERS {
+            return Err(FireJailError::InvalidProfile(format!(
+                "{} DNS servers configured, but firejail uses at most {}", self.profile.dns.len(), MAX_DNS_SERVERS)));
+        }
+        if let Some(netns) = &self.profile.netns {
+            if self.profile.networks.is_specified() {
+                return Err(FireJailError::ConflictingOptions(format!(
+                    "--netns={} joins an existing network namespace, so --net cannot set one up", netns)));
+            }
+        }
+        self.profile.netfilter.check("--netfilter")?;
+        self.profile.netfilter6.check("--netfilter6")?;
+        self.check_binds()?;
+        self.check_whitelist()?;
+        if !matches!(self.profile.overlay, Overlay::NoSpecified)
+            && !matches!(self.profile.private, Private::NoSpecified) {
+            return Err(FireJailError::ConflictingOptions(
+                "an overlay filesystem cannot be combined with --private".to_string()));
+        }
+        let known = || PROTOCOL_NAMES.iter().map(|(_, name)| *name);
+        if let Some(name) = self.profile.protocol.iter().find(|x| !known().any(|name| name == x.as_ref())) {
+            return Err(FireJailError::InvalidProfile(format!(
+                "unknown protocol family {:?}, expected one of {}", name.as_ref(), known().collect::<Vec<_>>().join(", "))));
+        }
+        if self.profile.sandbox_env.iter().any(|(key, _)| key.is_empty()) {
+            return Err(FireJailError::InvalidProfile("sandbox environment variable with an empty name".to_string()));
+        }
+        if let Some((key, _)) = self.profile.sandbox_env.iter()
+            .find(|(key, val)| ["LANG", "TZ"].contains(&key.as_ref()) && val.is_empty()) {
+            return Err(FireJailError::InvalidProfile(format!("empty value for sandbox environment variable {}", key)));
+        }
+        if let Net::Interfaces((_, interfaces)) = &self.profile.networks {
+            let mut macs = Vec::new();
+            let mut ips = Vec::new();
+            for interface in interfaces {
+                if let IpConfig::AddressRange(range) = &interface.ip_config {
+                    if parse_ip_range(range).is_none() {
+                        return Err(FireJailError::InvalidProfile(format!(
+                            "IP range {:?} is not a start-end pair of IPv4 addresses", range.as_ref())));
+                    }
+                }
+                if let Some(mac) = &interface.mac {
+                    if macs.contains(&mac) {
+                        return Err(FireJailError::ConflictingOptions(format!("MAC address {} is used by several interfaces", mac)));
+                    }
+                    macs.push(mac);
+                }
+                let addresses = match &interface.ip_config {
+                    IpConfig::Address(ip) => Some(ip),
+                    _ => None,
+                }.into_iter().chain(&interface.ip6);
+                for ip in addresses {
+                    if ips.contains(&ip) {
+                        return Err(FireJailError::ConflictingOptions(format!("IP address {} is used by several interfaces", ip)));
+                    }
+                    ips.push(ip);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if self.profile.private_cache && !matches!(self.profile.private, Private::NoSpecified) {
+            warnings.push(Warning::new(
+                "private-cache-redundant",
+                "--private-cache has no effect when --private already replaces the home directory",
+            ));
+        }
+        if !self.profile.dns.is_empty() && matches!(self.profile.networks, Net::None) {
+            warnings.push(Warning::new("dns-ignored", "--dns is left out because networking is disabled"));
+        }
+        if !matches!(self.profile.networks, Net::Interfaces(_)) {
+            for (filter, flag) in [(&self.profile.netfilter, "--netfilter"), (&self.profile.netfilter6, "--netfilter6")] {
+                if !matches!(filter, NetFilter::Disable) {
+                    warnings.push(Warning::new(
+                        "netfilter-ignored",
+                        format!("{} has no effect without a network namespace from --net", flag),
+                    ));
+                }
+            }
+        }
+        if self.current_dir.is_some() && !matches!(self.profile.private_cwd, Private::NoSpecified) {
+            warnings.push(Warning::new(
+                "current-dir-ignored",
+                "--private-cwd sets the working directory inside the sandbox, so current_dir only applies to firejail",
+            ));
+        }
+        if self.profile.noroot && matches!(self.profile.networks, Net::Interfaces(_)) {
+            warnings.push(Warning::new(
+                "noroot-with-net",
+                "--noroot together with --net fails on kernels which cannot nest the user and network namespaces",
+            ));
+        }
+        // An overlay covers the whole root filesystem, so every tmpfs lies within it.
+        if !matches!(self.profile.overlay, Overlay::NoSpecified) {
+            for path in &self.profile.tmpfs {
+                warnings.push(Warning::new(
+                    "tmpfs-in-overlay",
+                    format!("--tmpfs={} is mounted inside the overlay filesystem", path.display()),
+                ));
+            }
+        }
+        if self.profile.hostname.is_some() && self.profile.join.is_some() {
+            warnings.push(Warning::new(
+                "hostname-ignored",
+                "--hostname is ignored with --join, which reuses the namespaces of a running sandbox",
+            ));
+        }
+        warnings
+    }
+
+    /// Lint the configuration without running it.
+    ///
+    /// Besides the warnings `validate` reports, this flags options which are deprecated or
+    /// experimental, which need firejail to run as root, and, if firejail is installed, which
+    /// rely on features the installed firejail was built without. The installed firejail is
+    /// queried only once per process.
+    pub fn check(&self) -> Vec<Warning> {
+        let mut warnings = self.warnings();
+        if self.profile.cgroup.is_some() {
+            warnings.push(Warning::new("deprecated", "--cgroup was removed in firejail 0.9.72"));
+        }
+        if !matches!(self.profile.private_lib, PrivateList::NoSpecified) {
+            warnings.push(Warning::new("experimental", "--private-lib is experimental and may break programs"));
+        }
+        if !self.profile.bind.is_empty() {
+            warnings.push(Warning::new("needs-root", "--bind is only available when running as root"));
+        }
+        if !self.profile.interface.is_empty() {
+            warnings.push(Warning::new("needs-root", "--interface is only available when running as root"));
+        }
+        if let Some(output) = installed_version_output() {
+            warnings.extend(self.feature_warnings(&disabled_features(output)));
+            if let Some(version) = parse_version(output) {
+                warnings.extend(self.version_warnings(version));
+            }
+        }
+        warnings
+    }
+
+    fn version_warnings(&self, version: (u32, u32, u32)) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if self.profile.keep_dev_shm && version < KEEP_DEV_SHM_VERSION {
+            let (major, minor, patch) = KEEP_DEV_SHM_VERSION;
+            warnings.push(Warning::new(
+                "unsupported-version",
+                format!("--keep-dev-shm needs firejail {}.{}.{} or later", major, minor, patch),
+            ));
+        }
+        warnings
+    }
+
+    fn feature_warnings(&self, disabled: &[String]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        let mut require = |used: bool, feature: &str, flag: &str| {
+            if used && disabled.iter().any(|x| x.eq_ignore_ascii_case(feature)) {
+                warnings.push(Warning::new(
+                    "feature-disabled",
+                    format!("{} needs {} support, which the installed firejail lacks", flag, feature),
+                ));
+            }
+        };
+        require(self.profile.apparmor, "AppArmor", "--apparmor");
+        warnings
+    }
+
+    /// Emit set-like list fields in sorted order.
+    ///
+    /// When enabled, `--blacklist`, `--dns`, `--cpu` and `--private-*` entries are sorted so
+    /// that the generated arguments do not depend on insertion order. Order-sensitive fields
+    /// such as `--bind` are always emitted as inserted.
+    pub fn sorted(&mut self, yes: bool) -> &mut Self {
+        self.sorted = yes;
+        self
+    }
+
+    /// Warn, through the warning hook, about program arguments which look like firejail flags.
+    ///
+    /// Everything passed to `arg` ends up after `--`, so `.arg("--net=none")` hands the flag
+    /// to the program instead of configuring the sandbox.
+    pub fn warn_on_suspicious_args(&mut self, yes: bool) -> &mut Self {
+        self.warn_on_suspicious_args = yes;
+        self
+    }
+
+    fn suspicious_args(&self) -> Vec<Warning> {
+        self.arg_vec.iter()
+            .map(|arg| arg.to_string_lossy())
+            .filter(|arg| {
+                let flag = arg.split('=').next().unwrap_or_default();
+                FIREJAIL_FLAGS.contains(&flag)
+            })
+            .map(|arg| Warning::new(
+                "suspicious-arg",
+                format!("{} is passed to {}, not to firejail", arg, self.executable.to_string_lossy()),
+            ))
+            .collect()
+    }
+
+    /// The arguments that would be passed to `firejail`, including the `--` separator,
+    /// the executable and its arguments.
+    ///
+    /// `spawn` uses the same arguments, so this allows inspecting a command without
+    /// firejail installed.
+    pub fn to_args(&self) -> Vec<OsString> {
+        self.build_args()
+    }
+
+    fn build_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        if !self.profile.verbose {
+            args.push("--quiet".into());
+        }
+        if self.profile.caps {
+            args.push("--caps".into());
+        }
+        if self.profile.allusers {
+            args.push("--allusers".into());
+        }
+        if self.profile.apparmor {
+            args.push("--apparmor".into());
+        }
+        if self.profile.appimage {
+            args.push("--appimage".into());
+        }
+        if self.profile.deterministic_exit_code {
+            args.push("--deterministic-exit-code".into());
+        }
+        if self.profile.disable_mnt {
+            args.push("--disable-mnt".into());
+        }
+
+        match &self.profile.caps_drop {
+            CapsDrop::DropAll => { args.push("--caps.drop=all".into()); }
+            CapsDrop::Settings { whitelist, blacklist } =>
+                {
+                    if !whitelist.is_empty() {
+                        let w = whitelist.join(",");
+                        args.push(format!("--caps.keep={}", w).into());
+                    }
+                    if !blacklist.is_empty() {
+                        let b = blacklist.join(",");
+                        args.push(format!("--caps.drop={}", b).into());
+                    }
+                }
+            _ => ()
+        }
+
+        if !self.profile.protocol.is_empty() {
+            args.push(format!("--protocol={}", canonical_protocols(&self.profile.protocol).join(",")).into());
+        }
+
+        match &self.profile.seccomp {
+            Seccomp::Enable => { args.push("--seccomp".into()); }
+            Seccomp::BlockSecondary => { args.push("--seccomp.block-secondary".into()); }
+            Seccomp::List(calls) if calls.is_empty() => { args.push("--seccomp".into()); }
+            Seccomp::List(calls) => { args.push(format!("--seccomp={}", calls.join(",")).into()); }
+            Seccomp::Drop(calls) if !calls.is_empty() => {
+                args.push(format!("--seccomp.drop={}", calls.join(",")).into());
+            }
+            Seccomp::Keep(calls) if !calls.is_empty() => {
+                args.push(format!("--seccomp.keep={}", calls.join(",")).into());
+            }
+            _ => ()
+        }
+        if self.profile.memory_deny_write_execute {
+            args.push("--memory-deny-write-execute".into());
+        }
+        if self.profile.nonewprivs {
+            args.push("--nonewprivs".into());
+        }
+        if self.profile.noroot {
+            args.push("--noroot".into());
+        }
+        if self.profile.nogroups {
+            args.push("--nogroups".into());
+        }
+
+        if let Some(g) = &self.profile.cgroup {
+            args.push(format!("--cgroup={}", g).into());
+        }
+
+        if let Some(j) = &self.profile.join {
+            args.push(format!("--join={}", j).into());
+        }
+
+        if let Some(j) = &self.profile.join_network {
+            args.push(format!("--join-network={}", j).into());
+        }
+
+        if let Some(j) = &self.profile.join_fs {
+            args.push(format!("--join-filesystem={}", j).into());
+        }
+
+        if let Some(j) = &self.profile.join_or_start {
+            args.push(format!("--join-or-start={}", j).into());
+        }
+
+        if let Some(n) = &self.profile.name {
+            args.push(format!("--name={}", n).into());
+        }
+
+        if let Some(h) = &self.profile.hostname {
+            args.push(format!("--hostname={}", h).into());
+        }
+
+        if let Some(h) = &self.profile.hosts_file {
+            args.push(path_arg("--hosts-file", h));
+        }
+
+        for (key, val) in &self.profile.sandbox_env {
+            args.push(format!("--env={}={}", key, val).into());
+        }
+
+        if let Some(t) = &self.profile.timeout {
+            args.push(format!("--timeout={}", t).into());
+        }
+
+        if let Some(n) = self.profile.nice {
+            args.push(format!("--nice={}", n).into());
+        }
+
+        if !self.profile.cpu.is_empty() {
+            let mut cpu = self.profile.cpu.clone();
+            if self.sorted {
+                cpu.sort_unstable();
+            }
+            args.push(format!("--cpu={}",
+                              cpu.iter()
+                                  .map(|x|format!("{}", x))
+                                  .collect::<Vec<_>>().join(",")).into());
+        }
+
+
+        if let Some(n) = self.profile.rlimit {
+            args.push(format!("--rlimit-as={}", n).into());
+        }
+        if let Some(n) = self.profile.rlimit_cpu {
+            args.push(format!("--rlimit-cpu={}", n).into());
+        }
+        if let Some(n) = self.profile.rlimit_fsize {
+            args.push(format!("--rlimit-fsize={}", n).into());
+        }
+        if let Some(n) = self.profile.rlimit_nofile {
+            args.push(format!("--rlimit-nofile={}", n).into());
+        }
+        if let Some(n) = self.profile.rlimit_nproc {
+            args.push(format!("--rlimit-nproc={}", n).into());
+        }
+        if let Some(n) = self.profile.rlimit_sigpending {
+            args.push(format!("--rlimit-sigpending={}", n).into());
+        }
+
+        for (a, b) in &self.profile.bind {
+            let mut arg = path_arg("--bind", a);
+            arg.push(",");
+            arg.push(b);
+            args.push(arg);
+        }
+
+        match &self.profile.networks {
+            Net::None => { args.push("--net=none".into()); }
+            Net::Interfaces((name, interfaces)) => {
+                args.push(format!("--net={}", name).into());
+                args.extend(interfaces.iter().flat_map(InterfaceConfig::to_args));
+            }
+            #[allow(deprecated)]
+            Net::NotSpecified | Net::NotSpecfied => ()
+        }
+
+        if let Some(netns) = &self.profile.netns {
+            args.push(format!("--netns={}", netns).into());
+        }
+
+        for name in &self.profile.interface {
+            args.push(format!("--interface={}", name).into());
+        }
+
+        match self.profile.tunnel.as_deref() {
+            Some("") => { args.push("--tunnel".into()); }
+            Some(tunnel) => { args.push(format!("--tunnel={}", tunnel).into()); }
+            None => ()
+        }
+
+        args.extend(self.profile.netfilter.to_arg("--netfilter"));
+        args.extend(self.profile.netfilter6.to_arg("--netfilter6"));
+
+        // firejail rejects --dns without a network, so leave it out for --net=none.
+        if !matches!(self.profile.networks, Net::None) {
+            let mut dns: Vec<_> = self.profile.dns.iter().collect();
+            if self.sorted {
+                dns.sort();
+            }
+            for server in dns {
+                args.push(format!("--dns={}", server).into());
+            }
+        }
+
+        for a in &self.profile.noblacklist {
+            args.push(path_arg("--noblacklist", a));
+        }
+
+        let mut blacklists: Vec<_> = self.profile.blacklists.iter().collect();
+        if self.sorted {
+            blacklists.sort();
+        }
+        for a in blacklists {
+            args.push(path_arg("--blacklist", a));
+        }
+
+        for a in &self.profile.whitelist {
+            args.push(path_arg("--whitelist", a));
+        }
+
+        for a in &self.profile.nowhitelist {
+            args.push(path_arg("--nowhitelist", a));
+        }
+
+        for a in &self.profile.tmpfs {
+            args.push(path_arg("--tmpfs", a));
+        }
+
+        for a in &self.profile.read_only {
+            args.push(path_arg("--read-only", a));
+        }
+
+        for a in &self.profile.read_write {
+            args.push(path_arg("--read-write", a));
+        }
+
+        let mut noexec: Vec<_> = self.profile.noexec.iter().collect();
+        if self.profile.noexec_writable {
+            let writable = self.profile.bind.iter()
+                .map(|(_, b)| b)
+                .filter(|b| !self.profile.read_only.contains(b))
+                .chain(&self.profile.tmpfs)
+                .chain(&self.profile.read_write);
+            for a in writable {
+                if !noexec.contains(&a) {
+                    noexec.push(a);
+                }
+            }
+        }
+        for a in noexec {
+            args.push(path_arg("--noexec", a));
+        }
+
+        for i in &self.profile.ignore {
+            args.push(format!("--ignore={}", i).into());
+        }
+
+        match &self.profile.overlay {
+            Overlay::Default => { args.push("--overlay".into()); }
+            Overlay::Tmp => { args.push("--overlay-tmp".into()); }
+            Overlay::Named(name) => { args.push(format!("--overlay-named={}", name).into()); }
+            _ => ()
+        }
+
+        match &self.profile.private {
+            Private::Default => { args.push("--private".into()); }
+            Private::Directory(dir) => { args.push(path_arg("--private", dir)); }
+            _ => ()
+        }
+
+        match &self.profile.private_cwd {
+            Private::Default => { args.push("--private-cwd".into()); }
+            Private::Directory(dir) => { args.push(path_arg("--private-cwd", dir)); }
+            _ => ()
+        }
+
+        if self.profile.private_cache {
+            args.push("--private-cache".into());
+        }
+
+        if self.profile.private_dev {
+            args.push("--private-dev".into());
+        }
+
+        if self.profile.private_tmp {
+            args.push("--private-tmp".into());
+        }
+
+        if self.profile.keep_dev_shm {
+            args.push("--keep-dev-shm".into());
+        }
+
+        if self.profile.keep_var_tmp {
+            args.push("--keep-var-tmp".into());
+        }
+
+        if self.profile.no3d {
+            args.push("--no3d".into());
+        }
+
+        if self.profile.noautopulse {
+            args.push("--noautopulse".into());
+        }
+
+        if self.profile.nodbus {
+            args.push("--nodbus".into());
+        }
+
+        if self.profile.nodvd {
+            args.push("--nodvd".into());
+        }
+
+        if self.profile.nosound {
+            args.push("--nosound".into());
+        }
+
+        if self.profile.notv {
+            args.push("--notv".into());
+        }
+
+        if self.profile.nou2f {
+            args.push("--nou2f".into());
+        }
+
+        if self.profile.novideo {
+            args.push("--novideo".into());
+        }
+
+        if !matches!(self.profile.private_bin, PrivateList::Empty) {
+            args.extend(self.profile.private_bin.to_arg("--private-bin", self.sorted));
+        }
+        args.extend(self.profile.private_etc.to_arg("--private-etc", self.sorted));
+        args.extend(self.profile.private_home.to_arg("--private-home", self.sorted));
+        args.extend(self.profile.private_lib.to_arg("--private-lib", self.sorted));
+        args.extend(self.profile.private_opt.to_arg("--private-opt", self.sorted));
+        args.extend(self.profile.private_srv.to_arg("--private-srv", self.sorted));
+
+        if self.profile.writable_etc {
+            args.push("--writable-etc".into());
+        }
+
+        if self.profile.writable_run_user {
+            args.push("--writable-run-user".into());
+        }
+
+        if self.profile.writable_var {
+            args.push("--writable-var".into());
+        }
+
+        if self.profile.writable_var_log {
+            args.push("--writable-var-log".into());
+        }
+
+        args.extend(self.profile.raw_args.iter().map(|x| OsString::from(x.as_ref())));
+
+        args.push("--".into());
+        args.push(self.executable.clone());
+        args.extend(self.arg_vec.iter().cloned());
+        args
+    }
+
+    /// Materialize the `firejail` invocation. Standard I/O redirections are moved into it.
+    fn command(&mut self) -> Command {
+        self.consumed.set(true);
+        if self.warn_on_suspicious_args {
+            self.suspicious_args().iter().for_each(log_warning);
+        }
+        let mut command = Command::new("firejail");
+        command.args(self.build_args());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        if self.env_clear {
+            command.env_clear();
+        }
+        for (key, val) in &self.envs {
+            match val {
+                Some(val) => command.env(key, val),
+                None => command.env_remove(key),
+            };
+        }
+        if let Some(cfg) = self.stdin.take() {
+            command.stdin(cfg);
+        }
+        if let Some(cfg) = self.stdout.take() {
+            command.stdout(cfg);
+        }
+        if let Some(cfg) = self.stderr.take() {
+            command.stderr(cfg);
+        }
+        command
+    }
+
+    /// Terminate the sandbox when the `FireJailChild` is dropped before it exits, e.g. on an
+    /// early return or a panic: firejail is sent `SIGTERM`, then `SIGKILL` if it is still
+    /// running after a grace period of two seconds.
+    ///
+    /// Signalling firejail rather than the sandboxed program is enough. firejail passes
+    /// `SIGTERM` on to the sandbox and shuts it down; if it is killed outright, the kernel
+    /// kills the sandbox with it, as firejail sets it up to die with its parent.
+    pub fn kill_on_drop(&mut self, yes: bool) -> &mut Self {
+        self.kill_on_drop = yes;
+        self
+    }
+
+    /// Run `f` after the sandbox spawned next has exited, e.g. to remove overlay storage or
+    /// temporary files it used. If spawning fails, `f` runs right away.
+    pub fn on_exit<F: FnOnce() + Send + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_exit.push(Box::new(f));
+        self
+    }
+
+    /// Start the sandbox. Conflicting or invalid options are rejected as by `validate`
+    /// before anything runs, a missing `firejail` binary is reported as `NotInstalled`, and
+    /// an argument list the kernel would reject as `ArgumentListTooLong`.
+    pub fn spawn(&mut self) -> FireJailResult<FireJailChild> {
+        self.consumed.set(true);
+        let on_exit = std::mem::take(&mut self.on_exit);
+        let deadline = self.profile.timeout.as_ref().map(|t| Instant::now() + t.as_duration());
+        let spawned = self.conflicts()
+            .and_then(|_| self.check_arg_length())
+            .and_then(|_| self.command().spawn().map_err(spawn_error));
+        match spawned {
+            Ok(child) => Ok(FireJailChild {
+                child: Some(child),
+                on_exit,
+                kill_on_drop: self.kill_on_drop,
+                deadline,
+                timed_out: false,
+            }),
+            Err(e) => {
+                on_exit.into_iter().for_each(|hook| hook());
+                Err(e)
+            }
+        }
+    }
+
+    /// Run the sandbox to completion and return its exit status, like `Command::status`.
+    ///
+    /// Stdio configured on the command is used as is; anything unset is inherited. firejail
+    /// exits with the status of the sandboxed program, but may also exit non-zero on its
+    /// own. With `deterministic_exit_code` the status is that of the program's own process
+    /// rather than of whichever process in the sandbox exited last.
+    pub fn status(&mut self) -> FireJailResult<ExitStatus> {
+        Ok(self.spawn()?.wait()?)
+    }
+
+    /// Run the sandbox to completion and collect its output, like `Command::output`.
+    ///
+    /// Unless configured otherwise, stdout and stderr are captured and stdin is null. Both
+    /// pipes are drained concurrently, so a chatty stderr cannot block the sandbox.
+    pub fn output(&mut self) -> FireJailResult<Output> {
+        Ok(self.output_timed()?.0)
+    }
+
+    /// `output`, also returning whether the sandbox was killed for timing out.
+    fn output_timed(&mut self) -> FireJailResult<(Output, bool)> {
+        if self.stdin.is_none() {
+            self.stdin(Stdio::null());
+        }
+        if self.stdout.is_none() {
+            self.stdout(Stdio::piped());
+        }
+        if self.stderr.is_none() {
+            self.stderr(Stdio::piped());
+        }
+        Ok(self.spawn()?.wait_with_output_timed()?)
+    }
+
+    /// Run the sandbox to completion, capturing its output, and report failures of firejail
+    /// itself as errors. The configuration is validated first and warnings are logged.
+    ///
+    /// A non-zero exit of the sandboxed program is not an error; check the returned status.
+    /// Redirections are kept as with `output`, but firejail's failures can only be recognized
+    /// while stderr is captured.
+    pub fn spawn_checked(&mut self) -> FireJailResult<SandboxOutput> {
+        self.validate()?.iter().for_each(log_warning);
+        let (output, timed_out) = self.output_timed()?;
+        if !output.status.success() && !timed_out {
+            if let Some(e) = classify_failure(&String::from_utf8_lossy(&output.stderr), output.status.code()) {
+                return Err(e);
+            }
+        }
+        Ok(SandboxOutput {
+            status: output.status,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            timed_out,
+        })
+    }
+}
+
+impl From<&str> for FireJailCommand {
+    fn from(program: &str) -> Self {
+        FireJailCommand::new(program)
+    }
+}
+
+impl From<String> for FireJailCommand {
+    fn from(program: String) -> Self {
+        FireJailCommand::new(program)
+    }
+}
+
+impl From<&Path> for FireJailCommand {
+    fn from(program: &Path) -> Self {
+        FireJailCommand::with_executable(program.as_os_str().to_os_string())
+    }
+}
+
+impl From<PathBuf> for FireJailCommand {
+    fn from(program: PathBuf) -> Self {
+        FireJailCommand::with_executable(program.into_os_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn firejail_available() -> bool {
+        Command::new("firejail").arg("--version").output().is_ok()
+    }
+
+    /// Warnings logged by any test; the hook is global, so filter by message.
+    fn logged_warnings() -> &'static std::sync::Mutex<Vec<Warning>> {
+        static LOGGED: OnceLock<std::sync::Mutex<Vec<Warning>>> = OnceLock::new();
+        LOGGED.get_or_init(|| {
+            set_warning_hook(|w| logged_warnings().lock().unwrap().push(w.clone()));
+            Default::default()
+        })
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("firejail-spawn-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test() {
+        let cpus = vec![0, 1];
+
+        let output = FireJailCommand::new("hostname")
+            .apparmor()
+            .caps()
+            .cpus(cpus)
+            .dns("8.8.8.8")
+            .dns("8.8.4.4")
+            .hostname("test")
+            .deterministic_exit_code()
+            .disable_mnt()
+            .hosts_file("/etc/hosts")
+            .caps_drop(
+                CapsDrop::builder()
+                    .blacklist("chown")
+                    .whitelist("fowner")
+                    .build())
+            .env("E", "2")
+            .output()
+            .unwrap();
+        println!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn private_etc_for_known_tool() {
+        let mut jail = FireJailCommand::new("curl");
+        jail.private_etc(vec!["hosts"]).private_etc_for("/usr/bin/curl");
+        match &jail.profile.private_etc {
+            PrivateList::Files(files) => {
+                for entry in &["hosts", "resolv.conf", "ssl", "ca-certificates", "passwd", "ld.so.cache"] {
+                    assert!(files.iter().any(|x| x == Path::new(entry)), "missing {}", entry);
+                }
+                assert_eq!(files.iter().filter(|x| *x == Path::new("hosts")).count(), 1);
+            }
+            _ => panic!("private_etc not populated"),
+        }
+    }
+
+    #[test]
+    fn sorted_args_are_order_independent() {
+        let mut a = FireJailCommand::new("true");
+        a.sorted(true)
+            .dns("8.8.8.8")
+            .dns("1.1.1.1")
+            .blacklist("/b")
+            .blacklist("/a")
+            .bind("/x", "/y")
+            .bind("/u", "/v");
+        let mut b = FireJailCommand::new("true");
+        b.sorted(true)
+            .blacklist("/a")
+            .dns("1.1.1.1")
+            .blacklist("/b")
+            .dns("8.8.8.8")
+            .bind("/x", "/y")
+            .bind("/u", "/v");
+        assert_eq!(a.to_args(), b.to_args());
+
+        let binds: Vec<_> = a.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--bind="))
+            .collect();
+        assert_eq!(binds, vec![OsString::from("--bind=/x,/y"), OsString::from("--bind=/u,/v")]);
+    }
+
+    #[test]
+    fn from_str_and_path() {
+        let mut jail: FireJailCommand = "true".into();
+        assert_eq!(jail.to_args().last(), Some(&OsString::from("true")));
+        let from_path: FireJailCommand = Path::new("/bin/true").into();
+        assert_eq!(from_path.to_args().last(), Some(&OsString::from("/bin/true")));
+        if firejail_available() {
+            assert!(jail.spawn().unwrap().wait().unwrap().success());
+        }
+    }
+
+    #[test]
+    fn whitelist_unblacklisted_emits_noblacklist_first() {
+        let mut jail = FireJailCommand::new("true");
+        jail.whitelist_unblacklisted("/home/user/.ssh");
+        let args = jail.to_args();
+        let noblacklist = args.iter().position(|x| x == "--noblacklist=/home/user/.ssh").unwrap();
+        let whitelist = args.iter().position(|x| x == "--whitelist=/home/user/.ssh").unwrap();
+        assert!(noblacklist < whitelist);
+    }
+
+    #[test]
+    fn unset_option_fields() {
+        let mut jail = FireJailCommand::new("true");
+        jail.hostname("box").name("sandbox").cgroup("/sys/fs/cgroup/g");
+        assert!(jail.to_args().contains(&OsString::from("--name=sandbox")));
+        jail.unset_hostname().unset_name().unset_cgroup();
+        let args = jail.to_args();
+        assert!(!args.iter().any(|x| {
+            let x = x.to_string_lossy();
+            x.starts_with("--hostname") || x.starts_with("--name") || x.starts_with("--cgroup")
+        }));
+
+        jail.netns("vpn").tunnel("");
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--netns=vpn")) && args.contains(&OsString::from("--tunnel")));
+        jail.unset_netns().unset_tunnel();
+        assert!(!jail.to_args().iter().any(|x| {
+            let x = x.to_string_lossy();
+            x.starts_with("--netns") || x.starts_with("--tunnel")
+        }));
+    }
+
+    #[test]
+    fn protocol_flags() {
+        let mut jail = FireJailCommand::new("true");
+        jail.protocol(Protocol::INET);
+        assert!(jail.to_args().contains(&OsString::from("--protocol=inet")));
+
+        let mut jail = FireJailCommand::new("true");
+        jail.protocol(Protocol::PACKET | Protocol::INET6)
+            .protocol(Protocol::UNIX | Protocol::INET6)
+            .protocol_name("bluetooth");
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--protocol=unix,inet6,packet,bluetooth")));
+        assert_eq!(args.iter().filter(|x| x.to_string_lossy().starts_with("--protocol")).count(), 1);
+    }
+
+    #[test]
+    fn private_cache() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().contains(&OsString::from("--private-cache")));
+        jail.private_cache();
+        assert!(jail.to_args().contains(&OsString::from("--private-cache")));
+        assert!(jail.validate().unwrap().is_empty());
+        jail.private(Private::Default);
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "private-cache-redundant"));
+    }
+
+    #[test]
+    fn profile_templates() {
+        let mut base = FireJailCommand::new("unused");
+        base.apparmor().caps_drop(CapsDrop::drop_all()).caps();
+        register_template("browser", base.profile().clone());
+
+        let jail = FireJailCommand::from_template("browser", "firefox").unwrap();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--apparmor")));
+        assert!(args.contains(&OsString::from("--caps.drop=all")));
+        assert_eq!(args.last(), Some(&OsString::from("firefox")));
+
+        match FireJailCommand::from_template("no-such-template", "firefox") {
+            Err(FireJailError::UnknownTemplate(name)) => assert_eq!(name, "no-such-template"),
+            _ => panic!("expected an unknown template error"),
+        }
+    }
+
+    #[test]
+    fn no_network() {
+        let mut jail = FireJailCommand::new("true");
+        jail.dns("8.8.8.8").no_network();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--net=none")));
+        assert!(!args.iter().any(|x| x.to_string_lossy().starts_with("--dns")));
+
+        let mut jail = FireJailCommand::new("true");
+        jail.protocol(Protocol::INET).dns("8.8.8.8").default_deny_network();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--net=none")));
+        assert!(args.contains(&OsString::from("--protocol=unix")));
+        assert!(!args.iter().any(|x| x.to_string_lossy().starts_with("--dns")));
+    }
+
+    #[test]
+    fn self_sandbox() {
+        if is_self_sandboxed() {
+            // Running as the helper started below: relaunching again must be refused.
+            assert!(FireJailCommand::self_sandbox().is_err());
+            return;
+        }
+        let mut jail = FireJailCommand::self_sandbox().unwrap();
+        let exe = std::env::current_exe().unwrap();
+        let args = jail.to_args();
+        let separator = args.iter().position(|x| x == "--").unwrap();
+        assert_eq!(args[separator + 1], exe.into_os_string());
+        assert_eq!(args[separator + 2..], std::env::args_os().skip(1).collect::<Vec<_>>()[..]);
+        let command = jail.command();
+        assert!(command.get_envs().any(|(k, v)| k == SELF_SANDBOX_ENV && v == Some(OsStr::new("1"))));
+
+        let helper = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "test::self_sandbox", "--test-threads=1"])
+            .env(SELF_SANDBOX_ENV, "1")
+            .output()
+            .unwrap();
+        assert!(helper.status.success(), "{}", String::from_utf8_lossy(&helper.stdout));
+        assert!(String::from_utf8_lossy(&helper.stdout).contains("1 passed"));
+    }
+
+    #[test]
+    fn lock_down_binary() {
+        let mut jail = FireJailCommand::new("sh");
+        jail.lock_down_binary().unwrap();
+        let sh = find_executable("sh").unwrap().canonicalize().unwrap();
+        let flag = OsString::from(format!("--read-only={}", sh.display()));
+        assert!(jail.to_args().contains(&flag));
+
+        let mut jail = FireJailCommand::new("no-such-binary-anywhere");
+        assert!(jail.lock_down_binary().is_err());
+    }
+
+    #[test]
+    fn stdout_file() {
+        let path = temp_path("stdout");
+        std::fs::write(&path, "old\n").unwrap();
+        let mut jail = FireJailCommand::new("echo");
+        jail.arg("hello").stdout_file(&path, FileMode::Append).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+        jail.stdout_file(&path, FileMode::Truncate).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        if firejail_available() {
+            jail.spawn().unwrap().wait().unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hostname_with_join() {
+        let mut jail = FireJailCommand::new("true");
+        jail.hostname("box");
+        assert!(jail.validate().unwrap().is_empty());
+        jail.join(Join::Name(InlinableString::from("browser")));
+        assert!(jail.to_args().contains(&OsString::from("--join=browser")));
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "hostname-ignored"));
+    }
+
+    #[test]
+    fn resource_limited() {
+        let mut jail = FireJailCommand::new("true");
+        jail.resource_limited(ResourceConfig {
+            memory: Some(512 * 1024 * 1024),
+            processes: Some(32),
+            cpus: vec![1],
+            ..Default::default()
+        });
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--rlimit-as=536870912")));
+        assert!(args.contains(&OsString::from("--rlimit-nproc=32")));
+        assert!(args.contains(&OsString::from("--cpu=1")));
+        assert!(!args.iter().any(|x| x.to_string_lossy().starts_with("--rlimit-cpu")));
+    }
+
+    #[test]
+    fn background() {
+        let mut jail = FireJailCommand::new("true");
+        jail.cpus(vec![0, 1]).background_with(15, vec![2, 3]);
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--nice=15")));
+        assert!(args.contains(&OsString::from("--cpu=2,3")));
+
+        let mut jail = FireJailCommand::new("true");
+        jail.background();
+        let last = *allowed_cpus().unwrap().last().unwrap();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--nice=10")));
+        assert!(args.contains(&OsString::from(format!("--cpu={}", last))));
+
+        assert_eq!(parse_cpu_list("4-5\n"), Some(vec![4, 5]));
+        assert_eq!(parse_cpu_list("0,2-3,7"), Some(vec![0, 2, 3, 7]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn insufficient_privileges() {
+        let stderr = "Error: cannot set up the sandbox: Operation not permitted\n";
+        match classify_failure(stderr, Some(1)) {
+            Some(FireJailError::InsufficientPrivileges(msg)) => assert_eq!(msg, stderr),
+            _ => panic!("expected an insufficient privileges error"),
+        }
+        assert!(matches!(classify_failure("Error: you need to be root to use this option\n", Some(1)),
+                         Some(FireJailError::InsufficientPrivileges(_))));
+        assert!(classify_failure("cat: /root/x: Permission denied\n", Some(1)).is_none());
+        match classify_failure("Error: invalid --cpu argument\n", Some(1)) {
+            Some(FireJailError::Firejail { code, stderr }) => assert_eq!((code, stderr.as_str()), (1, "Error: invalid --cpu argument\n")),
+            _ => panic!("expected a firejail error"),
+        }
+    }
+
+    #[test]
+    fn program_errors_are_not_firejail_errors() {
+        for (stderr, code) in [
+            ("error: could not compile `demo`\n", Some(1)),
+            ("Error reading config.toml\n", Some(1)),
+            ("  Error: indented by the program\n", Some(1)),
+            ("warning: unused\nError: second line\n", Some(1)),
+            ("Error: permission denied\n", Some(2)),
+            ("Error: invalid input\n", Some(101)),
+            ("Error: killed\n", None),
+        ] {
+            assert!(classify_failure(stderr, code).is_none(), "{:?} {:?}", stderr, code);
+        }
+        assert!(matches!(classify_failure("\nError: cannot mount tmpfs: Permission denied\n", Some(1)),
+                         Some(FireJailError::InsufficientPrivileges(_))));
+    }
+
+    #[test]
+    fn writable_log_file() {
+        let mut jail = FireJailCommand::new("true");
+        jail.writable_log_file("/var/log/app.log");
+        let binds: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--bind="))
+            .collect();
+        assert_eq!(binds, vec![OsString::from("--bind=/var/log/app.log,/var/log/app.log")]);
+        assert!(!jail.to_args().contains(&OsString::from("--writable-var-log")));
+    }
+
+    #[test]
+    fn profile_matrix() {
+        let mut base = FireJailCommand::new("true");
+        base.apparmor().dns("8.8.8.8");
+        let mut a = FireJailCommand::new("unused");
+        a.hostname("a").dns("1.1.1.1");
+        let mut b = FireJailCommand::new("unused");
+        b.private(Private::Default);
+        let variations = vec![a.profile().clone(), b.profile().clone()];
+
+        let args: Vec<_> = matrix(base.clone(), variations.clone()).map(|x| x.to_args()).collect();
+        assert_eq!(args.len(), 2);
+        assert_ne!(args[0], args[1]);
+        for flag in &["--apparmor", "--hostname=a", "--dns=8.8.8.8", "--dns=1.1.1.1"] {
+            assert!(args[0].contains(&OsString::from(flag)));
+        }
+        assert!(args[1].contains(&OsString::from("--private")));
+        assert!(!args[1].contains(&OsString::from("--hostname=a")));
+
+        if firejail_available() {
+            for child in spawn_matrix(base, variations) {
+                child.unwrap().wait().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn keep_dev() {
+        let mut jail = FireJailCommand::new("true");
+        jail.private_dev().nosound().no3d().keep_dev(Device::Shm).keep_dev(Device::Dri);
+        let args = jail.to_args();
+        let devices: Vec<_> = args.iter()
+            .filter(|x| ["--private-dev", "--keep-dev-shm", "--no3d", "--nosound"].contains(&x.to_str().unwrap()))
+            .collect();
+        assert_eq!(devices, ["--private-dev", "--keep-dev-shm", "--nosound"]);
+
+        let logged = logged_warnings();
+        FireJailCommand::new("true").keep_dev(Device::Video);
+        assert_eq!(logged.lock().unwrap().iter()
+            .filter(|x| x.code == "keep-dev-redundant" && x.message.contains("--novideo"))
+            .count(), 1);
+
+        assert_eq!(jail.version_warnings((0, 9, 64))[0].code, "unsupported-version");
+        assert!(jail.version_warnings((0, 9, 72)).is_empty());
+    }
+
+    #[test]
+    fn check_warnings() {
+        let mut jail = FireJailCommand::new("true");
+        jail.cgroup("/sys/fs/cgroup/g/tasks").bind("/a", "/b").apparmor();
+        let warnings = jail.check();
+        assert!(warnings.iter().any(|x| x.code == "deprecated" && x.message.contains("--cgroup")));
+        assert!(warnings.iter().any(|x| x.code == "needs-root" && x.message.contains("--bind")));
+
+        let version = "firejail version 0.9.72\n\nCompile time support:\n\t- AppArmor support is disabled\n\t- networking support is enabled\n";
+        let disabled = disabled_features(version);
+        assert_eq!(disabled, vec!["AppArmor".to_string()]);
+        let features = jail.feature_warnings(&disabled);
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].code, "feature-disabled");
+    }
+
+    #[test]
+    fn warn_on_suspicious_args() {
+        let logged = logged_warnings();
+        let mut jail = FireJailCommand::new("suspicious-program");
+        jail.arg("--private").arg("-v").warn_on_suspicious_args(true);
+        jail.command();
+        let warnings: Vec<_> = logged.lock().unwrap().iter()
+            .filter(|x| x.message.contains("suspicious-program"))
+            .cloned()
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "suspicious-arg");
+        assert!(warnings[0].message.contains("--private"));
+    }
+
+    #[test]
+    fn timeout() {
+        use std::os::unix::process::ExitStatusExt;
+        let mut jail = FireJailCommand::new("sleep");
+        jail.arg("10").timeout(Timeout::from_secs(1));
+        assert!(jail.to_args().contains(&OsString::from("--timeout=00:00:01")));
+
+        let slow = Command::new("sleep").arg("10").spawn().unwrap();
+        let deadline = Some(Instant::now() + Duration::from_millis(200));
+        let mut child = FireJailChild { child: Some(slow), on_exit: Vec::new(), kill_on_drop: false, deadline, timed_out: false };
+        assert_eq!(child.wait().unwrap().signal(), Some(15));
+        assert!(child.timed_out());
+
+        // Exiting after the deadline has passed, but before it is checked, is not a timeout.
+        let late = Command::new("true").spawn().unwrap();
+        let deadline = Some(Instant::now() + Duration::from_millis(200));
+        std::thread::sleep(Duration::from_millis(300));
+        let mut child = FireJailChild { child: Some(late), on_exit: Vec::new(), kill_on_drop: false, deadline, timed_out: false };
+        assert!(child.wait().unwrap().success());
+        assert!(!child.timed_out());
+
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_path("slow-firejail");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("firejail"), "#!/bin/sh\necho started\nexec /bin/sleep 10\n").unwrap();
+        std::fs::set_permissions(dir.join("firejail"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut slow = FireJailCommand::new("sleep");
+        slow.arg("10").timeout(Timeout::from_secs(1)).env("PATH", &dir);
+        let output = slow.spawn_checked().unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.stdout, b"started\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        if firejail_available() {
+            assert!(jail.spawn_checked().unwrap().timed_out);
+        }
+    }
+
+    #[test]
+    fn on_exit() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        let ran = Arc::new(AtomicBool::new(false));
+        let hook = ran.clone();
+        let mut jail = FireJailCommand::new("true");
+        jail.on_exit(move || hook.store(true, Ordering::SeqCst));
+        match jail.spawn() {
+            Ok(mut child) => {
+                assert!(!ran.load(Ordering::SeqCst));
+                child.wait().unwrap();
+            }
+            Err(_) => assert!(!firejail_available()),
+        }
+        assert!(ran.load(Ordering::SeqCst));
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let hook = ran.clone();
+        let child = Command::new("true").spawn().unwrap();
+        let mut child = FireJailChild { child: Some(child), on_exit: vec![Box::new(move || hook.store(true, Ordering::SeqCst))], kill_on_drop: false, deadline: None, timed_out: false };
+        child.wait().unwrap();
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn private_lib_auto() {
+        let ldd = "\tlinux-vdso.so.1 (0x00007ffd)\n\
+                   \tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f19)\n\
+                   \tlibmissing.so.1 => not found\n\
+                   \t/lib64/ld-linux-x86-64.so.2 (0x00007f19)\n";
+        assert_eq!(ldd_libraries(ldd), vec!["libc.so.6", "ld-linux-x86-64.so.2"]);
+
+        let mut jail = FireJailCommand::new("sh");
+        jail.private_lib_auto();
+        match &jail.profile.private_lib {
+            PrivateList::Files(libs) => assert!(libs.iter().any(|x| x.to_string_lossy().starts_with("libc."))),
+            _ => panic!("no libraries collected"),
+        }
+
+        let mut jail = FireJailCommand::new("no-such-binary-anywhere");
+        jail.private_lib_auto();
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--private-lib")));
+    }
+
+    #[test]
+    fn dns_without_network() {
+        let mut jail = FireJailCommand::new("true");
+        jail.net(Net::None).dns("8.8.8.8");
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--net=none")));
+        assert!(!args.contains(&OsString::from("--dns=8.8.8.8")));
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "dns-ignored"));
+    }
+
+    #[test]
+    fn from_profile_file() {
+        let path = temp_path("sample.profile");
+        std::fs::write(&path, "\
+# sample profile
+quiet
+caps.drop all
+noroot
+blacklist /boot
+dns 1.1.1.1
+protocol unix,inet
+x11 none
+").unwrap();
+        let jail = FireJailCommand::from_profile_file("true", &path).unwrap();
+        let args = jail.to_args();
+        assert!(!args.contains(&OsString::from("--caps")));
+        for flag in &["--caps.drop=all", "--blacklist=/boot", "--dns=1.1.1.1",
+                      "--protocol=unix,inet", "--noroot", "--x11=none"] {
+            assert!(args.contains(&OsString::from(flag)), "missing {}", flag);
+        }
+        let separator = args.iter().position(|x| x == "--").unwrap();
+        assert!(args.iter().position(|x| x == "--noroot").unwrap() < separator);
+
+        std::fs::write(&path, "nice low\n").unwrap();
+        match FireJailCommand::from_profile_file("true", &path) {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.ends_with(":1: nice expects a number, got \"low\"")),
+            _ => panic!("expected an invalid profile error"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn typed_capabilities() {
+        let mut jail = FireJailCommand::new("true");
+        jail.caps().caps_drop(
+            CapsDrop::builder()
+                .drop(Capability::SysAdmin)
+                .drop(Capability::NetRaw)
+                .keep(Capability::Chown)
+                .build());
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--caps.drop=sys_admin,net_raw")));
+        assert!(args.contains(&OsString::from("--caps.keep=chown")));
+        assert_eq!(Capability::DacReadSearch.name(), "dac_read_search");
+    }
+
+    #[test]
+    fn close_stdin() {
+        let mut jail = FireJailCommand::new("cat");
+        assert!(jail.stdin.is_none());
+        jail.close_stdin();
+        assert!(jail.stdin.is_some());
+        if firejail_available() {
+            let start = Instant::now();
+            jail.spawn().unwrap().wait().unwrap();
+            assert!(start.elapsed() < Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn tmpfs_in_overlay() {
+        let mut jail = FireJailCommand::new("true");
+        jail.tmpfs("/var/cache");
+        assert!(jail.validate().unwrap().is_empty());
+        jail.overlay(Overlay::Tmp);
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--overlay-tmp")));
+        assert!(args.contains(&OsString::from("--tmpfs=/var/cache")));
+        let warnings = jail.validate().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "tmpfs-in-overlay");
+    }
+
+    #[test]
+    fn duplicate_interface_addresses() {
+        let interface = |mac: &str, ip: &str| InterfaceConfig {
+            mac: Some(mac.into()),
+            ip_config: IpConfig::Address(ip.into()),
+            ..Default::default()
+        };
+        let mut jail = FireJailCommand::new("true");
+        jail.net(Net::Interfaces(("br0".into(), vec![
+            interface("00:11:22:33:44:55", "10.0.0.2"),
+            interface("00:11:22:33:44:66", "10.0.0.3"),
+        ])));
+        assert!(jail.validate().is_ok());
+
+        jail.net(Net::Interfaces(("br0".into(), vec![
+            interface("00:11:22:33:44:55", "10.0.0.2"),
+            interface("00:11:22:33:44:55", "10.0.0.3"),
+        ])));
+        match jail.validate() {
+            Err(FireJailError::ConflictingOptions(msg)) => assert!(msg.contains("00:11:22:33:44:55")),
+            _ => panic!("expected conflicting options"),
+        }
+
+        jail.net(Net::Interfaces(("br0".into(), vec![
+            interface("00:11:22:33:44:55", "10.0.0.2"),
+            interface("00:11:22:33:44:66", "10.0.0.2"),
+        ])));
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
+    }
+
+    #[test]
+    fn hardened() {
+        let mut jail = FireJailCommand::new("true");
+        jail.hardened();
+        let args = jail.to_args();
+        for flag in &["--caps.drop=all", "--seccomp", "--nonewprivs", "--noroot", "--nogroups"] {
+            assert!(args.contains(&OsString::from(flag)), "missing {}", flag);
+        }
+        assert!(args.contains(&OsString::from(format!("--rlimit-nproc={}", HARDENED_NPROC))));
+
+        let mut jail = FireJailCommand::new("true");
+        jail.hardened_with(ResourceConfig { processes: Some(16), ..Default::default() });
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-nproc=16")));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn never_spawned_warning() {
+        let logged = logged_warnings();
+        let count = |program: &str| logged.lock().unwrap().iter()
+            .filter(|x| x.code == "never-spawned" && x.message.contains(program))
+            .count();
+        FireJailCommand::new("configured-but-dropped").apparmor();
+        assert_eq!(count("configured-but-dropped"), 1);
+        drop(FireJailCommand::new("plain-and-dropped"));
+        assert_eq!(count("plain-and-dropped"), 0);
+        FireJailCommand::new("inspected-and-dropped").apparmor().to_args();
+        assert_eq!(count("inspected-and-dropped"), 1);
+        let _ = format!("{:?}", FireJailCommand::new("debugged-and-dropped").apparmor());
+        assert_eq!(count("debugged-and-dropped"), 1);
+    }
+
+    #[test]
+    fn seccomp_with_extra_keep() {
+        let mut jail = FireJailCommand::new("true");
+        jail.seccomp(Seccomp::Enable).seccomp_with_extra_keep(vec!["ptrace", "personality"]);
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--seccomp=!ptrace,!personality")));
+        assert!(!args.iter().any(|x| x.to_string_lossy().starts_with("--seccomp.keep")));
+    }
+
+    #[test]
+    fn seccomp_with_extra_keep_replacing_filter() {
+        let logged = logged_warnings();
+        let count = |program: &str| logged.lock().unwrap().iter()
+            .filter(|x| x.code == "seccomp-replaced" && x.message.contains(program))
+            .count();
+        let mut jail = FireJailCommand::new("seccomp-enabled");
+        jail.seccomp(Seccomp::Enable).seccomp_with_extra_keep(["ptrace"]).seccomp_with_extra_keep(["bpf"]);
+        assert_eq!(count("seccomp-enabled"), 0);
+        assert!(jail.to_args().contains(&OsString::from("--seccomp=!ptrace,!bpf")));
+
+        let mut jail = FireJailCommand::new("seccomp-dropping");
+        jail.seccomp(Seccomp::Drop(vec!["mount".into()])).seccomp_with_extra_keep(["ptrace"]);
+        assert_eq!(count("seccomp-dropping"), 1);
+        assert!(jail.to_args().contains(&OsString::from("--seccomp=!ptrace")));
+    }
+
+    #[test]
+    fn sandbox_stats() {
+        // The inner loop stops once its parent is gone, so killing the outer shell is enough.
+        let busy = Command::new("sh").arg("-c")
+            .arg("sh -c 'while kill -0 $PPID 2>/dev/null; do :; done' & wait")
+            .spawn().unwrap();
+        let mut child = FireJailChild { child: Some(busy), on_exit: Vec::new(), kill_on_drop: false, deadline: None, timed_out: false };
+        std::thread::sleep(Duration::from_millis(500));
+        let stats = child.stats().unwrap();
+        assert!(stats.cpu_time > Duration::from_millis(0));
+        assert!(stats.rss_bytes > 0);
+        assert!(stats.num_procs >= 2);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(child.stats().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+        if firejail_available() {
+            let mut jail = FireJailCommand::new("sh");
+            jail.arg("-c").arg("while :; do :; done");
+            let mut child = jail.spawn().unwrap();
+            std::thread::sleep(Duration::from_millis(500));
+            assert!(child.stats().unwrap().cpu_time > Duration::from_millis(0));
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
+
+    #[test]
+    fn check_private_bin() {
+        let mut jail = FireJailCommand::new("sh");
+        assert!(jail.check_private_bin().is_empty());
+        jail.private_bin(["sh", "definitely-not-a-binary"]);
+        assert_eq!(jail.check_private_bin(), vec!["definitely-not-a-binary".to_string()]);
+        assert!(jail.to_args().contains(&"--private-bin=sh,definitely-not-a-binary".into()));
+    }
+
+    #[test]
+    fn locale_and_timezone() {
+        let mut jail = FireJailCommand::new("date");
+        jail.set_locale("en_US.UTF-8").set_timezone("Europe/Berlin");
+        let args = jail.to_args();
+        assert!(args.contains(&"--env=LANG=en_US.UTF-8".into()));
+        assert!(args.contains(&"--env=TZ=Europe/Berlin".into()));
+        assert!(jail.validate().is_ok());
+        jail.set_timezone("");
+        assert!(matches!(jail.validate(), Err(FireJailError::InvalidProfile(_))));
+
+        let mut jail = FireJailCommand::new("date");
+        jail.sandbox_env("FOO", "");
+        assert!(jail.validate().is_ok());
+        jail.sandbox_env("", "x");
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("empty name")),
+            _ => panic!("expected an invalid profile error"),
+        }
+    }
+
+    #[test]
+    fn immutable_root() {
+        let mut jail = FireJailCommand::new("ls");
+        jail.immutable_root();
+        let args: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--read-only=") || x.to_string_lossy().starts_with("--tmpfs="))
+            .collect();
+        assert_eq!(args, [
+            "--tmpfs=/tmp", "--tmpfs=/var/tmp",
+            "--read-only=/usr", "--read-only=/bin", "--read-only=/lib", "--read-only=/etc",
+        ].iter().map(OsString::from).collect::<Vec<_>>());
+
+        let mut jail = FireJailCommand::new("ls");
+        jail.immutable_root_with(["/opt"], ["/opt/cache"]);
+        let args = jail.to_args();
+        let tmpfs = args.iter().position(|x| x == "--tmpfs=/opt/cache").unwrap();
+        let read_only = args.iter().position(|x| x == "--read-only=/opt").unwrap();
+        assert!(tmpfs < read_only);
+    }
+
+    #[test]
+    fn argument_list_too_long() {
+        let mut jail = FireJailCommand::new("ls");
+        jail.blacklists((0..100).map(|i| format!("/var/lib/generated/{:06}", i)));
+        assert!(jail.validate().is_ok());
+
+        jail.blacklists((0..100_000).map(|i| format!("/var/lib/generated/{:06}", i)));
+        match jail.validate() {
+            Err(FireJailError::ArgumentListTooLong(size)) => assert!(size > ARG_BYTES_LIMIT),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(jail.spawn_checked(), Err(FireJailError::ArgumentListTooLong(_))));
+
+        let mut jail = FireJailCommand::new("ls");
+        jail.private_etc((0..20_000).map(|i| format!("generated-{:06}", i)));
+        assert!(matches!(jail.validate(), Err(FireJailError::ArgumentListTooLong(_))));
+        assert!(matches!(jail.spawn(), Err(FireJailError::ArgumentListTooLong(_))));
+    }
+
+    #[test]
+    fn profile_includes() {
+        let dir = temp_path("profiles");
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(dir.join("main.profile"), "\
+include common/base.inc
+include missing.local
+nice 5
+").unwrap();
+        std::fs::write(dir.join("common/base.inc"), "noroot\nblacklist /boot\n").unwrap();
+        let jail = FireJailCommand::from_profile_file_with_base("true", dir.join("main.profile"), &dir).unwrap();
+        let args = jail.to_args();
+        for flag in &["--noroot", "--blacklist=/boot", "--nice=5"] {
+            assert!(args.contains(&OsString::from(flag)), "missing {}", flag);
+        }
+
+        std::fs::write(dir.join("common/base.inc"), "noroot\ninclude ../main.profile\n").unwrap();
+        match FireJailCommand::from_profile_file("true", dir.join("main.profile")) {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("base.inc:2: include cycle through"), "{}", msg),
+            _ => panic!("expected an include cycle error"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn noexec_writable() {
+        let mut jail = FireJailCommand::new("ls");
+        jail.bind("/srv/data", "/data")
+            .bind("/srv/tools", "/tools")
+            .read_only("/tools")
+            .tmpfs("/scratch")
+            .noexec("/scratch");
+        assert!(!jail.to_args().contains(&"--noexec=/data".into()));
+
+        jail.noexec_writable(true);
+        let noexec: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--noexec="))
+            .collect();
+        assert_eq!(noexec, [OsString::from("--noexec=/scratch"), OsString::from("--noexec=/data")]);
+    }
+
+    #[test]
+    fn auto_name() {
+        let mut a = FireJailCommand::new("true");
+        let mut b = FireJailCommand::new("true");
+        let first = a.auto_name("worker");
+        let second = b.auto_name("worker");
+        assert_ne!(first, second);
+        assert!(first.starts_with(&format!("worker-{}-", std::process::id())));
+        assert!(a.to_args().contains(&format!("--name={}", first).into()));
+        assert!(b.to_args().contains(&format!("--name={}", second).into()));
+    }
+
+    #[test]
+    fn to_args_matches_spawned_command() {
+        let mut jail = FireJailCommand::new("to-args-ls");
+        jail.noroot().hostname("box").arg("-l").arg("/tmp");
+        let args = jail.to_args();
+        assert_eq!(args.first(), Some(&OsString::from("--quiet")));
+        assert_eq!(&args[args.len() - 4..], ["--", "to-args-ls", "-l", "/tmp"].iter().map(OsString::from).collect::<Vec<_>>().as_slice());
+        assert!(args.iter().position(|x| x == "--noroot") < args.iter().position(|x| x == "--hostname=box"));
+
+        let command = jail.command();
+        assert_eq!(command.get_program(), "firejail");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), args.iter().map(OsString::as_os_str).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn status() {
+        let mut jail = FireJailCommand::new("true");
+        match jail.status() {
+            Ok(status) => assert!(status.success()),
+            Err(e) => {
+                assert!(!firejail_available());
+                assert!(matches!(e, FireJailError::NotInstalled), "{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_version() {
+        let output = "firejail version 0.9.72\n\nCompile time support:\n\t- AppArmor support is disabled\n";
+        assert_eq!(super::parse_version(output), Some((0, 9, 72)));
+        assert_eq!(super::parse_version("firejail version 0.9.73-rc1\n"), Some((0, 9, 73)));
+        assert_eq!(super::parse_version("firejail version 0.9\n"), None);
+        assert_eq!(super::parse_version("usage: firejail [options]\n"), None);
+        match firejail_version() {
+            Ok(version) => assert!(version >= (0, 9, 0)),
+            Err(e) => assert!(!firejail_available() && matches!(e, FireJailError::NotInstalled), "{}", e),
+        }
+    }
+
+    #[test]
+    fn clone_configures_independently() {
+        let mut base = FireJailCommand::new("clone-base");
+        base.noroot().arg("-v").env("KEY", "value").current_dir("/tmp");
+        let mut a = base.clone();
+        let mut b = base.clone();
+        a.hostname("a");
+        b.net(Net::None);
+
+        let (base_args, a_args, b_args) = (base.to_args(), a.to_args(), b.to_args());
+        assert!(a_args.contains(&"--hostname=a".into()) && !a_args.contains(&"--net=none".into()));
+        assert!(b_args.contains(&"--net=none".into()) && !b_args.contains(&"--hostname=a".into()));
+        assert!(!base_args.contains(&"--hostname=a".into()) && !base_args.contains(&"--net=none".into()));
+        for args in &[&a_args, &b_args] {
+            assert!(args.contains(&"--noroot".into()) && args.ends_with(&["clone-base".into(), "-v".into()]));
+        }
+        let command = a.command();
+        assert_eq!(command.get_current_dir(), Some(Path::new("/tmp")));
+        assert!(command.get_envs().any(|(k, v)| k == "KEY" && v == Some(OsStr::new("value"))));
+    }
+
+    #[test]
+    fn debug_shows_command_line() {
+        let mut jail = FireJailCommand::new("debug-echo");
+        jail.noroot().arg("hello world").arg("it's").arg("");
+        assert_eq!(format!("{:?}", jail), r#"firejail --quiet --noroot -- debug-echo 'hello world' 'it'\''s' ''"#);
+    }
+
+    #[test]
+    fn shared_profile() {
+        let mut builder = FireJailCommand::new("unused");
+        builder.noroot().net(Net::None);
+        let restricted = builder.into_profile();
+        for program in &["ls", "cat"] {
+            let jail = FireJailCommand::with_profile(program, restricted.clone());
+            let args = jail.to_args();
+            assert!(args.contains(&"--noroot".into()) && args.contains(&"--net=none".into()));
+            assert!(args.ends_with(&["--".into(), OsString::from(program)]));
+            assert!(jail.profile().noroot);
+        }
+    }
+
+    #[test]
+    fn kill_on_drop() {
+        let sleep = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = sleep.id();
+        let start = Instant::now();
+        drop(FireJailChild { child: Some(sleep), on_exit: Vec::new(), kill_on_drop: true, deadline: None, timed_out: false });
+        assert!(start.elapsed() < KILL_GRACE_PERIOD);
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+
+        let stubborn = Command::new("sh").arg("-c").arg("trap '' TERM; while :; do sleep 0.1; done").spawn().unwrap();
+        let pid = stubborn.id();
+        std::thread::sleep(Duration::from_millis(100));
+        drop(FireJailChild { child: Some(stubborn), on_exit: Vec::new(), kill_on_drop: true, deadline: None, timed_out: false });
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+
+        if firejail_available() {
+            let mut jail = FireJailCommand::new("sleep");
+            jail.arg("30").kill_on_drop(true);
+            let child = jail.spawn().unwrap();
+            let pid = child.id();
+            drop(child);
+            assert!(!Path::new(&format!("/proc/{}", pid)).exists());
         }
     }
 
-    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
-        self.inner.current_dir(dir);
-        self
+    #[test]
+    fn caps_drop_without_caps() {
+        let mut jail = FireJailCommand::new("true");
+        jail.caps_drop(CapsDrop::drop_all());
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--caps.drop=all")));
+        assert!(!args.contains(&OsString::from("--caps")));
+        jail.caps();
+        assert!(jail.to_args().contains(&OsString::from("--caps")));
     }
 
-    pub fn cpu(&mut self, no: usize) -> &mut Self {
-        self.profile.cpu.push(no);
-        self
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_whilelist() {
+        let mut jail = FireJailCommand::new("true");
+        jail.caps_drop(CapsDrop::builder().whilelist("chown").whilelists(["fowner"]).build());
+        assert!(jail.to_args().contains(&OsString::from("--caps.keep=chown,fowner")));
+        jail.caps_drop(CapsDrop::builder().whitelist("chown").whitelists(["fowner"]).build());
+        assert!(jail.to_args().contains(&OsString::from("--caps.keep=chown,fowner")));
     }
 
-    pub fn cpus<N: AsPrimitive<usize>, I : IntoIterator<Item = N>>(&mut self, iter: I) -> &mut Self {
-        self.profile.cpu.extend(iter.into_iter().map(|x|x.as_()));
-        self
+    #[test]
+    fn protocol_names() {
+        let mut jail = FireJailCommand::new("true");
+        jail.protocol_names(["inet6", "bluetooth", "unix"]).protocol_name("inet");
+        let args = jail.to_args();
+        assert_eq!(args.iter().filter(|x| x.to_string_lossy().starts_with("--protocol")).count(), 1);
+        assert!(args.contains(&OsString::from("--protocol=unix,inet,inet6,bluetooth")));
+        assert!(jail.validate().is_ok());
+        jail.protocol_name("inet4");
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("\"inet4\"")),
+            _ => panic!("expected an unknown protocol error"),
+        }
     }
 
-    pub fn blacklist<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
-        self.profile.blacklists.push(path.as_ref().to_path_buf());
-        self
+    #[test]
+    fn bind_path_with_comma() {
+        let mut jail = FireJailCommand::new("ls");
+        jail.bind("/tmp/a,b", "/mnt");
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("/tmp/a,b")),
+            _ => panic!("expected an invalid bind error"),
+        }
+        let ran = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let hook = ran.clone();
+        jail.on_exit(move || hook.store(true, Ordering::SeqCst));
+        assert!(matches!(jail.spawn(), Err(FireJailError::InvalidProfile(_))));
+        assert!(ran.load(Ordering::SeqCst));
+
+        let mut jail = FireJailCommand::new("ls");
+        jail.bind("/tmp/a", "/mnt/a,b");
+        assert!(jail.validate().is_err());
     }
 
-    pub fn blacklists<I, P: AsRef<Path>>(&mut self, paths: I) -> &mut Self where
-        I: IntoIterator<Item=P>
-    {
-        self.profile.blacklists.extend(paths.into_iter().map(|x| x.as_ref().to_path_buf()));
-        self
+    #[test]
+    fn non_utf8_paths() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        let mut jail = FireJailCommand::new("ls");
+        jail.blacklist(path).hosts_file(path).bind(path, "/mnt").private_etc([path]);
+        let args = jail.to_args();
+        for expected in [&b"--blacklist=/tmp/caf\xe9"[..], b"--hosts-file=/tmp/caf\xe9",
+                         b"--bind=/tmp/caf\xe9,/mnt", b"--private-etc=/tmp/caf\xe9"] {
+            assert!(args.contains(&OsString::from_vec(expected.to_vec())), "missing {:?}", OsStr::from_bytes(expected));
+        }
+
+        let jail = FireJailCommand::from(path);
+        assert_eq!(jail.to_args().last(), Some(&path.as_os_str().to_os_string()));
     }
 
-    pub fn bind<A: AsRef<Path>, B: AsRef<Path>>(&mut self, a: A, b: B) -> &mut Self {
-        self.profile.bind.push((a.as_ref().to_path_buf(), b.as_ref().to_path_buf()));
-        self
+    #[test]
+    #[allow(deprecated)]
+    fn net_serialization() {
+        let mut jail = FireJailCommand::new("true");
+        let plain = jail.to_args();
+        assert!(!plain.iter().any(|x| x.to_string_lossy().starts_with("--net")));
+        jail.net(Net::Interfaces(("br0".into(), vec![InterfaceConfig::default()])));
+        assert!(jail.to_args().contains(&OsString::from("--net=br0")));
+        jail.net(Net::None);
+        assert!(jail.to_args().contains(&OsString::from("--net=none")));
+        jail.net(Net::NotSpecfied);
+        assert_eq!(jail.to_args(), plain);
+        assert!(matches!(jail.profile().networks, Net::NotSpecified));
+        assert!(matches!(Net::NotSpecfied, Net::NotSpecfied));
+
+        let old = Profile { networks: Net::NotSpecfied, ..Default::default() };
+        jail.net(Net::None).merge(&old);
+        assert!(jail.to_args().contains(&OsString::from("--net=none")));
+        jail.profile.networks = Net::NotSpecfied;
+        jail.netns("vpn");
+        assert!(jail.validate().is_ok());
+        assert_eq!(jail.to_args(), FireJailCommand::new("true").netns("vpn").to_args());
     }
 
-    pub fn binds<I, A: AsRef<Path>, B: AsRef<Path>>(&mut self, binds: I) -> &mut Self where
-        I: IntoIterator<Item=(A, B)>
-    {
-        self.profile.bind.extend(binds.into_iter().map(|(a, b)| (
-            a.as_ref().to_path_buf(), b.as_ref().to_path_buf()
-        )));
-        self
+    #[test]
+    fn interface_config() {
+        let interface = InterfaceConfig::builder()
+            .default_gw("10.10.20.1")
+            .mac("8c:89:a5:d2:b1:2e")
+            .ip("10.10.20.5")
+            .ip6("2001:db8::5")
+            .mtu(1492)
+            .netmask("255.255.255.0")
+            .veth_name("jail0")
+            .build();
+        let mut jail = FireJailCommand::new("true");
+        jail.net(Net::Interfaces(("br0".into(), vec![interface])));
+        let args = jail.to_args();
+        let net = args.iter().position(|x| x == "--net=br0").unwrap();
+        assert_eq!(&args[net..net + 8], [
+            "--net=br0", "--defaultgw=10.10.20.1", "--mac=8c:89:a5:d2:b1:2e", "--ip=10.10.20.5",
+            "--ip6=2001:db8::5", "--mtu=1492", "--netmask=255.255.255.0", "--veth-name=jail0",
+        ].iter().map(OsString::from).collect::<Vec<_>>().as_slice());
+
+        let range = InterfaceConfig::builder().ip("10.10.20.5").ip_range("10.10.20.50", "10.10.20.99").build();
+        jail.net(Net::Interfaces(("br0".into(), vec![range])));
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--iprange=10.10.20.50,10.10.20.99")));
+        assert!(!args.contains(&OsString::from("--ip=10.10.20.5")));
     }
 
-    pub fn args<I, S>(&mut self, args: I) -> &mut Self where
-        I: IntoIterator<Item=S>,
-        S: AsRef<str> {
-        self.arg_vec.extend(args.into_iter().map(|x| InlinableString::from(x.as_ref())));
-        self
+    #[test]
+    fn interfaces() {
+        let mut jail = FireJailCommand::new("true");
+        jail.interface("eth0").interface("eth1");
+        let interfaces: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--interface"))
+            .collect();
+        assert_eq!(interfaces, [OsString::from("--interface=eth0"), OsString::from("--interface=eth1")]);
+        jail.interfaces(["wlan0"]);
+        assert!(jail.to_args().contains(&OsString::from("--interface=wlan0")));
     }
 
-    pub fn dns<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
-        self.profile.dns.push(InlinableString::from(arg.as_ref()));
-        self
+    #[test]
+    fn netfilter() {
+        let mut jail = FireJailCommand::new("true");
+        let netfilter = |jail: &FireJailCommand| jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--netfilter"))
+            .collect::<Vec<_>>();
+        assert!(netfilter(&jail).is_empty());
+        jail.netfilter(NetFilter::Default);
+        assert_eq!(netfilter(&jail), [OsString::from("--netfilter")]);
+
+        let path = temp_path("netfilter.net");
+        std::fs::write(&path, "*filter\nCOMMIT\n").unwrap();
+        jail.netfilter(NetFilter::WithSetting { path: path.clone(), args: None });
+        assert_eq!(netfilter(&jail), [path_arg("--netfilter", &path)]);
+        assert!(jail.validate().is_ok());
+        jail.netfilter(NetFilter::WithSetting { path: path.clone(), args: Some(vec!["22".into(), "80".into()]) });
+        assert_eq!(netfilter(&jail), [OsString::from(format!("--netfilter={},22,80", path.display()))]);
+
+        std::fs::remove_file(&path).unwrap();
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("does not exist")),
+            _ => panic!("expected a missing netfilter file error"),
+        }
+        jail.netfilter(NetFilter::Disable);
+        assert!(netfilter(&jail).is_empty());
     }
 
-    pub fn dnss<I, S>(&mut self, args: I) -> &mut Self where
-        I: IntoIterator<Item=S>,
-        S: AsRef<str> {
-        self.profile.dns.extend(args.into_iter().map(|x| InlinableString::from(x.as_ref())));
-        self
+    #[test]
+    fn netfilter6() {
+        let mut jail = FireJailCommand::new("true");
+        let netfilter6 = |jail: &FireJailCommand| jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--netfilter6"))
+            .collect::<Vec<_>>();
+        assert!(netfilter6(&jail).is_empty());
+        jail.netfilter6(NetFilter::Default);
+        assert_eq!(netfilter6(&jail), [OsString::from("--netfilter6")]);
+        assert!(!jail.to_args().contains(&OsString::from("--netfilter")));
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "netfilter-ignored" && x.message.contains("--netfilter6")));
+        jail.net(Net::Interfaces(("br0".into(), vec![])));
+        assert!(!jail.validate().unwrap().iter().any(|x| x.code == "netfilter-ignored"));
+
+        let path = temp_path("netfilter6.net");
+        std::fs::write(&path, "*filter\nCOMMIT\n").unwrap();
+        jail.netfilter6(NetFilter::WithSetting { path: path.clone(), args: Some(vec!["443".into()]) });
+        assert_eq!(netfilter6(&jail), [OsString::from(format!("--netfilter6={},443", path.display()))]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(jail.validate(), Err(FireJailError::InvalidProfile(_))));
+        jail.netfilter6(NetFilter::Disable);
+        assert!(netfilter6(&jail).is_empty());
     }
 
-    pub fn ignore<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
-        self.profile.ignore.push(InlinableString::from(arg.as_ref()));
-        self
+    #[test]
+    fn netns() {
+        let mut jail = FireJailCommand::new("true");
+        jail.netns("vpn");
+        assert!(jail.to_args().contains(&OsString::from("--netns=vpn")));
+        assert!(jail.validate().is_ok());
+        jail.net(Net::None);
+        match jail.validate() {
+            Err(FireJailError::ConflictingOptions(msg)) => assert!(msg.contains("--netns=vpn")),
+            _ => panic!("expected a netns conflict"),
+        }
     }
 
-    pub fn ignores<I, S>(&mut self, args: I) -> &mut Self where
-        I: IntoIterator<Item=S>,
-        S: AsRef<str> {
-        self.profile.ignore.extend(args.into_iter().map(|x| InlinableString::from(x.as_ref())));
-        self
+    #[test]
+    fn ip_ranges() {
+        let mut jail = FireJailCommand::new("true");
+        jail.net(Net::Interfaces(("br0".into(), vec![InterfaceConfig::builder().ip("10.10.20.5").build()])));
+        assert!(jail.to_args().contains(&OsString::from("--ip=10.10.20.5")));
+
+        for range in ["10.10.20.50-10.10.20.99", "10.10.20.50,10.10.20.99"] {
+            let interface = InterfaceConfig { ip_config: IpConfig::AddressRange(range.into()), ..Default::default() };
+            jail.net(Net::Interfaces(("br0".into(), vec![interface])));
+            assert!(jail.to_args().contains(&OsString::from("--iprange=10.10.20.50,10.10.20.99")));
+            assert!(jail.validate().is_ok());
+        }
+        for range in ["10.10.20.50", "10.10.20.99-10.10.20.50", "10.10.20.50-2001:db8::1", "a,b"] {
+            let interface = InterfaceConfig { ip_config: IpConfig::AddressRange(range.into()), ..Default::default() };
+            jail.net(Net::Interfaces(("br0".into(), vec![interface])));
+            assert!(matches!(jail.validate(), Err(FireJailError::InvalidProfile(_))), "{} accepted", range);
+        }
     }
 
-    pub fn arg<S: AsRef<str>>(&mut self, arg: S) -> &mut Self {
-        self.arg_vec.push(InlinableString::from(arg.as_ref()));
-        self
+    #[test]
+    fn tunnel() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--tunnel")));
+        jail.tunnel("tap0");
+        assert!(jail.to_args().contains(&OsString::from("--tunnel=tap0")));
+        jail.tunnel("");
+        assert!(jail.to_args().contains(&OsString::from("--tunnel")));
     }
 
-    pub fn env_clear(&mut self) -> &mut Self {
-        self.inner.env_clear();
-        self
+    #[test]
+    fn dns_validation() {
+        let mut jail = FireJailCommand::new("true");
+        jail.dns("9.9.9.9").dns("2620:fe::fe");
+        assert!(jail.validate().is_ok());
+        jail.dns("one.one.one.one");
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("one.one.one.one")),
+            _ => panic!("expected an invalid DNS server error"),
+        }
+
+        let mut jail = FireJailCommand::new("true");
+        jail.dnss(["1.1.1.1", "1.0.0.1", "8.8.8.8", "8.8.4.4"]);
+        match jail.validate() {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("4 DNS servers")),
+            _ => panic!("expected a too many DNS servers error"),
+        }
     }
 
-    pub fn env_remove<S: AsRef<OsStr>>(&mut self, key: S) -> &mut Self {
-        self.inner.env_remove(key);
-        self
+    #[test]
+    fn join() {
+        let mut jail = FireJailCommand::new("true");
+        jail.join(Join::Pid(4242));
+        assert!(jail.to_args().contains(&OsString::from("--join=4242")));
+        jail.join(Join::Name("browser".into()));
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--join=browser")) && !args.contains(&OsString::from("--join=4242")));
+        assert!(jail.validate().is_ok());
+        jail.net(Net::None);
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
     }
 
-    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
-        where
-            I: IntoIterator<Item=(K, V)>,
-            K: AsRef<OsStr>,
-            V: AsRef<OsStr>,
-    {
-        self.inner.envs(vars);
-        self
+    #[test]
+    fn join_network() {
+        let mut jail = FireJailCommand::new("true");
+        jail.join_network(Join::Pid(4242));
+        assert!(jail.to_args().contains(&OsString::from("--join-network=4242")));
+        jail.join_network(Join::Name("vpn".into()));
+        assert!(jail.to_args().contains(&OsString::from("--join-network=vpn")));
+        assert!(jail.validate().is_ok());
+        jail.join(Join::Name("vpn".into()));
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
     }
 
-    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut Self
-        where
-            K: AsRef<OsStr>,
-            V: AsRef<OsStr>,
-    {
-        self.inner.env(key, val);
-        self
+    #[test]
+    fn join_fs() {
+        let mut jail = FireJailCommand::new("ls");
+        jail.join_fs(Join::Pid(4242));
+        assert!(jail.to_args().contains(&OsString::from("--join-filesystem=4242")));
+        jail.join_fs(Join::Name("build".into()));
+        assert!(jail.to_args().contains(&OsString::from("--join-filesystem=build")));
+        assert!(jail.validate().is_ok());
+        jail.join(Join::Name("build".into()));
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
     }
 
-    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
-        self.inner.stderr(cfg);
-        self
+    #[test]
+    fn join_or_start() {
+        let mut jail = FireJailCommand::new("firefox");
+        jail.join_or_start("browser");
+        assert!(jail.to_args().contains(&OsString::from("--join-or-start=browser")));
+        assert!(jail.validate().is_ok());
+        jail.name("browser");
+        assert!(jail.validate().is_ok());
+        jail.name("other");
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
     }
 
-    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
-        self.inner.stdout(cfg);
-        self
+    #[test]
+    fn private_home() {
+        let mut jail = FireJailCommand::new("true");
+        let private = |jail: &FireJailCommand| jail.to_args().into_iter()
+            .filter(|x| x == "--private" || x.to_string_lossy().starts_with("--private="))
+            .collect::<Vec<_>>();
+        assert!(private(&jail).is_empty());
+        jail.private(Private::Default);
+        assert_eq!(private(&jail), [OsString::from("--private")]);
+        jail.private(Private::Directory("/home/user/sandbox".into()));
+        assert_eq!(private(&jail), [OsString::from("--private=/home/user/sandbox")]);
+        jail.private(Private::NoSpecified);
+        assert!(private(&jail).is_empty());
     }
 
-    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
-        self.inner.stdin(cfg);
-        self
+    #[test]
+    fn private_bin() {
+        let mut jail = FireJailCommand::new("sh");
+        jail.private_bin(Vec::<&str>::new());
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--private-bin")));
+        jail.private_bin(["sh", "/usr/bin/ls"]).private_bin(["cat"]);
+        assert!(jail.to_args().contains(&OsString::from("--private-bin=sh,ls,cat")));
+        jail.sorted(true);
+        assert!(jail.to_args().contains(&OsString::from("--private-bin=cat,ls,sh")));
     }
 
-    pub fn caps_drop(&mut self, cfg: CapsDrop) -> &mut Self {
-        self.profile.caps_drop = cfg;
-        self
+    #[test]
+    fn private_etc() {
+        let mut jail = FireJailCommand::new("true");
+        let private_etc = |jail: &FireJailCommand| jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--private-etc"))
+            .collect::<Vec<_>>();
+        assert!(private_etc(&jail).is_empty());
+        jail.private_etc(Vec::<&str>::new());
+        assert_eq!(private_etc(&jail), [OsString::from("--private-etc")]);
+        jail.private_etc(["hosts", "resolv.conf"]);
+        assert_eq!(private_etc(&jail), [OsString::from("--private-etc=hosts,resolv.conf")]);
     }
 
-    pub fn spawn(&mut self) -> Result<Child> {
-        if !self.profile.verbose {
-            self.inner.arg("--quiet");
-        }
-        if self.profile.caps {
-            self.inner.arg("--caps");
-        }
-        if self.profile.allusers {
-            self.inner.arg("--allusers");
-        }
-        if self.profile.apparmor {
-            self.inner.arg("--apparmor");
-        }
-        if self.profile.appimage {
-            self.inner.arg("--appimage");
-        }
-        if self.profile.deterministic_exit_code {
-            self.inner.arg("--deterministic-exit-code");
-        }
-        if self.profile.disable_mnt {
-            self.inner.arg("--disable-mnt");
-        }
+    #[test]
+    fn private_home_files() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--private-home")));
+        jail.private_home([".bashrc", "/home/user/.config/app"]);
+        assert!(jail.to_args().contains(&OsString::from("--private-home=.bashrc,/home/user/.config/app")));
+    }
 
-        if self.profile.caps {
-            match &self.profile.caps_drop {
-                CapsDrop::DropAll => { self.inner.arg("--caps.drop=all"); }
-                CapsDrop::Settings { whitelist, blacklist } =>
-                    {
-                        if !whitelist.is_empty() {
-                            let w = whitelist.join(",");
-                            self.inner.arg(format!("--caps.keep={}", w));
-                        }
-                        if !blacklist.is_empty() {
-                            let b = blacklist.join(",");
-                            self.inner.arg(format!("--caps.drop={}", b));
-                        }
-                    }
-                _ => ()
-            }
-        }
+    #[test]
+    fn private_lib() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.check().iter().any(|x| x.code == "experimental"));
+        jail.private_lib(Vec::<&str>::new());
+        assert!(jail.to_args().contains(&OsString::from("--private-lib")));
+        assert!(jail.check().iter().any(|x| x.code == "experimental" && x.message.contains("--private-lib")));
+        jail.private_lib(["libcurl.so.4", "libssl.so.3"]);
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--private-lib=libcurl.so.4,libssl.so.3")));
+        assert!(!args.contains(&OsString::from("--private-lib")));
+    }
 
-        if let Some(g) = &self.profile.cgroup {
-            self.inner.arg(format!("--cgroup={}", g));
-        }
+    #[test]
+    fn private_opt() {
+        let mut jail = FireJailCommand::new("true");
+        let private_opt = |jail: &FireJailCommand| jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--private-opt"))
+            .collect::<Vec<_>>();
+        assert!(private_opt(&jail).is_empty());
+        jail.private_opt(Vec::<&str>::new());
+        assert_eq!(private_opt(&jail), [OsString::from("--private-opt")]);
+        jail.private_opt(["google", "zoom"]);
+        assert_eq!(private_opt(&jail), [OsString::from("--private-opt=google,zoom")]);
+    }
 
-        if let Some(h) = &self.profile.hostname {
-            self.inner.arg(format!("--hostname={}", h));
-        }
+    #[test]
+    fn private_srv() {
+        let mut jail = FireJailCommand::new("true");
+        let plain = jail.to_args();
+        assert!(!plain.iter().any(|x| x.to_string_lossy().starts_with("--private-srv")));
+        jail.private_srv(Vec::<&str>::new());
+        assert!(jail.to_args().contains(&OsString::from("--private-srv")));
+        jail.private_srv(["www", "git"]);
+        assert!(jail.to_args().contains(&OsString::from("--private-srv=www,git")));
+    }
 
-        if let Some(h) = &self.profile.hosts_file {
-            self.inner.arg(format!("--hosts-file={}", h.display()));
-        }
+    #[test]
+    fn private_cwd() {
+        let mut jail = FireJailCommand::new("pwd");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--private-cwd")));
+        jail.private_cwd(Private::Default);
+        assert!(jail.to_args().contains(&OsString::from("--private-cwd")));
+        jail.private_cwd(Private::Directory("/srv/work".into()));
+        assert!(jail.to_args().contains(&OsString::from("--private-cwd=/srv/work")));
+        assert!(jail.validate().unwrap().is_empty());
+        jail.current_dir("/tmp");
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "current-dir-ignored"));
+    }
 
-        if !self.profile.cpu.is_empty() {
-            self.inner
-                .arg(format!("--cpu={}",
-                             self.profile.cpu.iter()
-                                 .map(|x|format!("{}", x))
-                                 .collect::<Vec<_>>().join(",")));
-        }
+    #[test]
+    fn private_flags() {
+        let flags = ["--private-cache", "--private-dev", "--private-tmp"];
+        let mut jail = FireJailCommand::new("true");
+        let args = jail.to_args();
+        assert!(flags.iter().all(|f| !args.contains(&OsString::from(f))));
+        jail.private_cache().private_dev().private_tmp();
+        let args = jail.to_args();
+        assert!(flags.iter().all(|f| args.contains(&OsString::from(f))));
+    }
+
+    #[test]
+    fn whitelist_paths() {
+        let mut jail = FireJailCommand::new("true");
+        jail.whitelists(["/srv/data", "~/.config/app"]).whitelist("${DOWNLOADS}");
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--whitelist=/srv/data")));
+        assert!(args.contains(&OsString::from("--whitelist=~/.config/app")));
+        assert!(args.contains(&OsString::from("--whitelist=${DOWNLOADS}")));
+        assert!(jail.validate().is_ok());
+        jail.whitelist("data/cache");
+        assert!(matches!(jail.validate(), Err(FireJailError::InvalidProfile(_))));
+    }
 
+    #[test]
+    fn nowhitelist_after_whitelist() {
+        let mut jail = FireJailCommand::new("true");
+        jail.nowhitelists(["~/Downloads", "~/Music"]).whitelist("~/Documents");
+        let args = jail.to_args();
+        let whitelist = args.iter().position(|x| x == "--whitelist=~/Documents").unwrap();
+        let downloads = args.iter().position(|x| x == "--nowhitelist=~/Downloads").unwrap();
+        let music = args.iter().position(|x| x == "--nowhitelist=~/Music").unwrap();
+        assert!(whitelist < downloads && downloads < music);
+    }
 
-        for (a, b) in &self.profile.bind {
-            self.inner.arg(format!("--bind={},{}", a.display(), b.display()));
-        }
+    #[test]
+    fn noblacklist_before_blacklist() {
+        let mut jail = FireJailCommand::new("true");
+        jail.blacklist("/mnt").blacklist("/media").noblacklists(["/media/usb"]);
+        let args = jail.to_args();
+        let noblacklist = args.iter().position(|x| x == "--noblacklist=/media/usb").unwrap();
+        assert!(args.iter().enumerate()
+            .filter(|(_, x)| x.to_string_lossy().starts_with("--blacklist="))
+            .all(|(i, _)| noblacklist < i));
+        assert_eq!(args.iter().filter(|x| x.to_string_lossy().starts_with("--blacklist=")).count(), 2);
+    }
 
-        for server in &self.profile.dns {
-            self.inner.arg(format!("--dns={}", server));
+    #[test]
+    fn read_only_paths() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let mut jail = FireJailCommand::new("true");
+        jail.read_onlys(["/etc", "/usr"]).read_only(OsStr::from_bytes(b"/srv/caf\xe9"));
+        let args = jail.to_args();
+        let read_only: Vec<_> = args.iter().filter(|x| x.as_bytes().starts_with(b"--read-only=")).collect();
+        assert_eq!(read_only, [
+            &OsString::from("--read-only=/etc"),
+            &OsString::from("--read-only=/usr"),
+            &OsString::from_vec(b"--read-only=/srv/caf\xe9".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn read_write_paths() {
+        let mut jail = FireJailCommand::new("true");
+        jail.read_writes(["/home/user/project", "/var/cache/app"]);
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--read-write=/home/user/project")));
+        assert!(args.contains(&OsString::from("--read-write=/var/cache/app")));
+    }
+
+    #[test]
+    fn read_write_overrides_read_only() {
+        let mut jail = FireJailCommand::new("true");
+        jail.read_write("/home/user/project").read_only("/home/user");
+        let args = jail.to_args();
+        let read_only = args.iter().position(|x| x == "--read-only=/home/user").unwrap();
+        let read_write = args.iter().position(|x| x == "--read-write=/home/user/project").unwrap();
+        assert!(read_only < read_write);
+    }
+
+    #[test]
+    fn noexec_paths() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let mut jail = FireJailCommand::new("true");
+        jail.noexecs(["/tmp", "/var/tmp"]).noexec(OsStr::from_bytes(b"/srv/caf\xe9"));
+        let noexec: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.as_bytes().starts_with(b"--noexec="))
+            .collect();
+        assert_eq!(noexec, [
+            OsString::from("--noexec=/tmp"),
+            OsString::from("--noexec=/var/tmp"),
+            OsString::from_vec(b"--noexec=/srv/caf\xe9".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn tmpfs_order() {
+        let mut jail = FireJailCommand::new("true");
+        jail.tmpfs("/home/user/.cache").tmpfses(["/home/user/tmp", "/home/user/.local/share/Trash"]);
+        let tmpfs: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--tmpfs="))
+            .collect();
+        assert_eq!(tmpfs, [
+            OsString::from("--tmpfs=/home/user/.cache"),
+            OsString::from("--tmpfs=/home/user/tmp"),
+            OsString::from("--tmpfs=/home/user/.local/share/Trash"),
+        ]);
+    }
+
+    #[test]
+    fn overlay_variants() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--overlay")));
+        for (overlay, flag) in [
+            (Overlay::Default, "--overlay"),
+            (Overlay::Tmp, "--overlay-tmp"),
+            (Overlay::Named("build".into()), "--overlay-named=build"),
+        ] {
+            jail.overlay(overlay);
+            let args = jail.to_args();
+            assert!(args.contains(&OsString::from(flag)));
+            assert_eq!(args.iter().filter(|x| x.to_string_lossy().starts_with("--overlay")).count(), 1);
         }
+        assert!(jail.validate().is_ok());
+        jail.private(Private::Default);
+        assert!(matches!(jail.validate(), Err(FireJailError::ConflictingOptions(_))));
+    }
+
+    #[test]
+    fn writable_flags() {
+        let flags = ["--writable-etc", "--writable-run-user", "--writable-var", "--writable-var-log"];
+        let mut jail = FireJailCommand::new("true");
+        let args = jail.to_args();
+        assert!(flags.iter().all(|f| !args.contains(&OsString::from(f))));
+        jail.writable_etc().writable_run_user().writable_var().writable_var_log();
+        let args = jail.to_args();
+        assert!(flags.iter().all(|f| args.contains(&OsString::from(f))));
+    }
+
+    #[test]
+    fn keep_flags() {
+        let mut jail = FireJailCommand::new("true");
+        let args = jail.to_args();
+        assert!(!args.contains(&OsString::from("--keep-dev-shm")));
+        assert!(!args.contains(&OsString::from("--keep-var-tmp")));
+        jail.keep_dev_shm().keep_var_tmp();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--keep-dev-shm")));
+        assert!(args.contains(&OsString::from("--keep-var-tmp")));
+    }
+
+    #[test]
+    fn seccomp_variants() {
+        let calls = || vec![InlinableString::from("mount"), InlinableString::from("umount2")];
+        let seccomp = |s: Seccomp| {
+            let mut jail = FireJailCommand::new("true");
+            jail.seccomp(s);
+            jail.to_args().into_iter()
+                .filter(|x| x.to_string_lossy().starts_with("--seccomp"))
+                .collect::<Vec<_>>()
+        };
+        assert!(seccomp(Seccomp::NotSpecified).is_empty());
+        assert_eq!(seccomp(Seccomp::Enable), ["--seccomp"]);
+        assert_eq!(seccomp(Seccomp::BlockSecondary), ["--seccomp.block-secondary"]);
+        assert_eq!(seccomp(Seccomp::List(calls())), ["--seccomp=mount,umount2"]);
+        assert_eq!(seccomp(Seccomp::List(vec![])), ["--seccomp"]);
+        assert_eq!(seccomp(Seccomp::Drop(calls())), ["--seccomp.drop=mount,umount2"]);
+        assert!(seccomp(Seccomp::Drop(vec![])).is_empty());
+        assert_eq!(seccomp(Seccomp::Keep(calls())), ["--seccomp.keep=mount,umount2"]);
+        assert!(seccomp(Seccomp::Keep(vec![])).is_empty());
+    }
+
+    #[test]
+    fn memory_deny_write_execute() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().contains(&OsString::from("--memory-deny-write-execute")));
+        jail.memory_deny_write_execute();
+        assert!(jail.to_args().contains(&OsString::from("--memory-deny-write-execute")));
+    }
+
+    #[test]
+    fn nonewprivs() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().contains(&OsString::from("--nonewprivs")));
+        jail.nonewprivs();
+        assert!(jail.to_args().contains(&OsString::from("--nonewprivs")));
+    }
 
-        for a in &self.profile.blacklists {
-            self.inner.arg(format!("--blacklist={}", a.display()));
+    #[test]
+    fn noroot() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().contains(&OsString::from("--noroot")));
+        jail.noroot();
+        assert!(jail.to_args().contains(&OsString::from("--noroot")));
+        assert!(jail.validate().unwrap().is_empty());
+        jail.net(Net::Interfaces(("br0".into(), vec![])));
+        assert!(jail.validate().unwrap().iter().any(|x| x.code == "noroot-with-net"));
+    }
+
+    #[test]
+    fn nogroups() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().contains(&OsString::from("--nogroups")));
+        jail.nogroups();
+        assert!(jail.to_args().contains(&OsString::from("--nogroups")));
+    }
+
+    #[test]
+    fn device_flags() {
+        let flags = ["--no3d", "--noautopulse", "--nodbus", "--nodvd", "--nosound", "--notv", "--nou2f", "--novideo"];
+        let mut jail = FireJailCommand::new("true");
+        let args = jail.to_args();
+        assert!(flags.iter().all(|f| !args.contains(&OsString::from(f))));
+        jail.no3d().noautopulse().nodbus().nodvd().nosound().notv().nou2f().novideo();
+        let args = jail.to_args();
+        for flag in &flags {
+            assert!(args.contains(&OsString::from(flag)), "missing {}", flag);
         }
+    }
 
-        for i in &self.profile.ignore {
-            self.inner.arg(format!("--ignore={}", i));
+    #[test]
+    fn protocol_tokens() {
+        let all = Protocol::UNIX | Protocol::INET | Protocol::INET6
+            | Protocol::NETLINK | Protocol::PACKET | Protocol::BLUETOOTH;
+        assert_eq!(all.names().collect::<Vec<_>>(), ["unix", "inet", "inet6", "netlink", "packet", "bluetooth"]);
+        assert_eq!(Protocol::BLUETOOTH.names().collect::<Vec<_>>(), ["bluetooth"]);
+        assert!(Protocol::empty().names().next().is_none());
+
+        let mut jail = FireJailCommand::new("true");
+        jail.protocol_set([Protocol::BLUETOOTH, Protocol::INET6, Protocol::UNIX, Protocol::INET6]);
+        assert!(jail.to_args().contains(&OsString::from("--protocol=unix,inet6,bluetooth")));
+        assert!(jail.validate().is_ok());
+    }
+
+    #[test]
+    fn rlimit_cpu() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--rlimit-cpu")));
+        jail.rlimit_cpu(30);
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-cpu=30")));
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("10m").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("1G").unwrap(), 1024 * 1024 * 1024);
+        for garbage in ["", "M", "ten", "10MB", "-1", "1.5G", "99999999999999999999G"] {
+            assert!(matches!(parse_size(garbage), Err(FireJailError::InvalidSize(_))), "{:?}", garbage);
         }
+    }
 
-        self.inner
-            .arg("--")
-            .arg(self.executable.as_ref())
-            .args(self.arg_vec.iter().map(|x| x.as_ref())).spawn()
+    #[test]
+    fn rlimit_fsize() {
+        let mut jail = FireJailCommand::new("true");
+        jail.rlimit_fsize(4096);
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-fsize=4096")));
+        jail.rlimit_fsize_str("10M").unwrap();
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-fsize=10485760")));
+        assert!(jail.rlimit_fsize_str("lots").is_err());
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-fsize=10485760")));
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::io::Read;
-    use std::process::Stdio;
+    #[test]
+    fn rlimit_nofile() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--rlimit-nofile")));
+        jail.rlimit_nofile(1024);
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-nofile=1024")));
+    }
 
-    use crate::FireJailCommand;
+    #[test]
+    fn rlimit_nproc() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--rlimit-nproc")));
+        jail.rlimit_nproc(64);
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-nproc=64")));
+    }
 
     #[test]
-    fn test() {
-        use super::*;
-        let mut out = String::new();
-        let cpus = vec![0, 1];
+    fn rlimit_sigpending() {
+        let mut jail = FireJailCommand::new("true");
+        assert!(!jail.to_args().iter().any(|x| x.to_string_lossy().starts_with("--rlimit-sigpending")));
+        jail.rlimit_sigpending(128);
+        assert!(jail.to_args().contains(&OsString::from("--rlimit-sigpending=128")));
+    }
 
-        let mut jail = FireJailCommand::new("hostname")
-            .apparmor()
-            .caps()
-            .cpus(cpus)
-            .dns("8.8.8.8")
-            .dns("8.8.4.4")
-            .hostname("test")
-            .deterministic_exit_code()
-            .disable_mnt()
-            .hosts_file("/etc/hosts")
-            .caps_drop(
-                CapsDrop::builder()
-                    .blacklist("chown")
-                    .whilelist("fowner")
-                    .build())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .env("E", "2")
-            .spawn()
-            .unwrap();
-        jail.stdout.as_mut().unwrap().read_to_string(&mut out).unwrap();
-        jail.stderr.as_mut().unwrap().read_to_string(&mut out).unwrap();
-        println!("{}", out);
+    #[test]
+    fn rlimits() {
+        let mut jail = FireJailCommand::new("true");
+        jail.rlimit_cpu(10).rlimits(Rlimits { nofile: Some(1024), nproc: Some(64), ..Default::default() });
+        let rlimits: Vec<_> = jail.to_args().into_iter()
+            .filter(|x| x.to_string_lossy().starts_with("--rlimit-"))
+            .collect();
+        assert_eq!(rlimits, [OsString::from("--rlimit-nofile=1024"), OsString::from("--rlimit-nproc=64")]);
+
+        jail.rlimit_as(1 << 20).resource_limited(ResourceConfig { processes: Some(8), ..Default::default() });
+        assert_eq!(jail.current_rlimits(), Rlimits { address_space: Some(1 << 20), nofile: Some(1024), nproc: Some(8), ..Default::default() });
+    }
+
+    #[test]
+    fn output_captures_by_default() {
+        use std::os::unix::fs::PermissionsExt;
+        // Command looks the program up in the PATH given to it, so this stands in for firejail.
+        let dir = temp_path("fake-firejail");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fake = dir.join("firejail");
+        std::fs::write(&fake, "#!/bin/sh\nread line\necho \"stdin:$line\"\necho stderr >&2\n").unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = FireJailCommand::new("true").env("PATH", &dir).output().unwrap();
+        assert_eq!(output.stdout, b"stdin:\n");
+        assert_eq!(output.stderr, b"stderr\n");
+
+        let output = FireJailCommand::new("true").env("PATH", &dir).stdout(Stdio::null()).output().unwrap();
+        assert!(output.stdout.is_empty());
+        assert_eq!(output.stderr, b"stderr\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profile_caps_drop_and_keep() {
+        let dir = temp_path("caps-profiles");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("caps.inc"), "caps.keep net_bind_service\n").unwrap();
+        std::fs::write(dir.join("main.profile"), "caps.drop sys_admin\ninclude caps.inc\ncaps.drop mknod\n").unwrap();
+        let jail = FireJailCommand::from_profile_file("true", dir.join("main.profile")).unwrap();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--caps.keep=net_bind_service")));
+        assert!(args.contains(&OsString::from("--caps.drop=sys_admin,mknod")));
+
+        std::fs::write(dir.join("main.profile"), "include caps.inc\ncaps.drop all\n").unwrap();
+        match FireJailCommand::from_profile_file("true", dir.join("main.profile")) {
+            Err(FireJailError::InvalidProfile(msg)) => assert!(msg.contains("conflicts with caps.keep"), "{}", msg),
+            _ => panic!("expected a caps conflict"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn spawn_rejects_conflicts() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_path("conflict-firejail");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fake = dir.join("firejail");
+        let marker = dir.join("ran");
+        std::fs::write(&fake, format!("#!/bin/sh\ntouch {}\n", marker.display())).unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut jail = FireJailCommand::new("true");
+        jail.env("PATH", &dir).netns("vpn").net(Net::None);
+        assert!(matches!(jail.spawn(), Err(FireJailError::ConflictingOptions(_))));
+        let mut jail = FireJailCommand::new("true");
+        jail.env("PATH", &dir).dns("not-an-ip");
+        assert!(matches!(jail.status(), Err(FireJailError::InvalidProfile(_))));
+        let mut jail = FireJailCommand::new("true");
+        jail.env("PATH", &dir).whitelist("relative/path");
+        assert!(matches!(jail.output(), Err(FireJailError::InvalidProfile(_))));
+        assert!(!marker.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nested_includes_in_subdirectories() {
+        let dir = temp_path("nested-includes");
+        std::fs::create_dir_all(dir.join("sub/deeper")).unwrap();
+        std::fs::write(dir.join("main.profile"), "include sub/first.inc\n").unwrap();
+        std::fs::write(dir.join("sub/first.inc"), "noroot\ninclude deeper/second.inc\n").unwrap();
+        std::fs::write(dir.join("sub/deeper/second.inc"), "nonewprivs\n").unwrap();
+        let jail = FireJailCommand::from_profile_file("true", dir.join("main.profile")).unwrap();
+        let args = jail.to_args();
+        assert!(args.contains(&OsString::from("--noroot")));
+        assert!(args.contains(&OsString::from("--nonewprivs")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}